@@ -0,0 +1,89 @@
+//! Compares convolving real signals through the real FFT with promoting them to complex numbers first, and
+//! reusing a `Convolver` with planning for every call.
+//! Run with `cargo bench --bench convolution`.
+
+use std::time::{Duration, Instant};
+
+use audio::convolution::{fft_convolve, rfft_convolve, Convolver, PartitionedConvolver};
+use audio::processor::process_offline;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+fn time<F: FnMut()>(iterations: u32, mut f: F) -> Duration {
+    // warm up the planner's caches and the allocator before timing
+    f();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed() / iterations
+}
+
+fn signal(len: usize) -> Vec<f32> {
+    (0..len).map(|i| ((i * 7919) % 1000) as f32 / 500. - 1.).collect()
+}
+
+fn main() {
+    let mut planner = FftPlanner::new();
+    for &(signal_len, kernel_len) in &[(4096, 512), (44100, 4096), (441000, 88200)] {
+        let signal = signal(signal_len);
+        let kernel = signal[..kernel_len].to_vec();
+        let iterations = (4_000_000 / signal_len) as u32;
+
+        let complex = time(iterations, || {
+            let signal: Vec<Complex<f32>> = signal.iter().map(|&x| Complex::new(x, 0.)).collect();
+            let kernel: Vec<Complex<f32>> = kernel.iter().map(|&x| Complex::new(x, 0.)).collect();
+            let convolved: Vec<f32> = fft_convolve(&signal, &kernel, &mut planner)
+                .into_iter()
+                .map(|x| x.re)
+                .collect();
+            assert_eq!(convolved.len(), signal_len + kernel_len - 1);
+        });
+        let real = time(iterations, || {
+            let convolved = rfft_convolve(&signal, &kernel, &mut planner);
+            assert_eq!(convolved.len(), signal_len + kernel_len - 1);
+        });
+        println!(
+            "convolve {:>6} x {:>5}: complex {:>10.3?}, real {:>10.3?}, speedup {:.2}x",
+            signal_len,
+            kernel_len,
+            complex,
+            real,
+            complex.as_secs_f64() / real.as_secs_f64()
+        );
+    }
+
+    // many short clips with the same kernel, where planning and allocation dominate
+    let clips: Vec<Vec<f32>> = (0..100).map(|i| signal(2000 + i)).collect();
+    let kernel = signal(1024);
+    let one_shot = time(10, || {
+        for clip in &clips {
+            rfft_convolve(clip, &kernel, &mut planner);
+        }
+    });
+    let mut convolver = Convolver::new(&kernel);
+    let mut output = vec![0.; convolver.output_len(clips[clips.len() - 1].len())];
+    let reused = time(10, || {
+        for clip in &clips {
+            let len = convolver.output_len(clip.len());
+            convolver.convolve_into(clip, &mut output[..len]);
+        }
+    });
+    println!(
+        "100 clips x  1024: rfft_convolve {:>10.3?}, Convolver {:>10.3?}, speedup {:.2}x",
+        one_shot,
+        reused,
+        one_shot.as_secs_f64() / reused.as_secs_f64()
+    );
+
+    let signal = signal(44100);
+    let kernel = signal[..8192].to_vec();
+    for &partition_size in &[64, 256, 1024] {
+        let mut convolver = PartitionedConvolver::new(&kernel, partition_size, &mut planner);
+        let mut samples = vec![signal.clone()];
+        let elapsed = time(10, || process_offline(&mut convolver, &mut samples, 44100, partition_size));
+        println!(
+            "partitioned 44100 x  8192, partition {:>4}: {:>10.3?} per second of audio",
+            partition_size, elapsed
+        );
+    }
+}
@@ -1,6 +1,20 @@
-use std::io::{Error, ErrorKind, Read, Seek, Write};
+use std::io::{Read, Seek, Write};
 
-use wav::{BitDepth, Header};
+use crate::resample::Resampler;
+
+mod channels;
+mod codec;
+mod dither;
+mod layout;
+mod stream;
+
+pub use codec::{Extensible, Header, SampleFormat, WAV_FORMAT_EXTENSIBLE, WAV_FORMAT_IEEE_FLOAT, WAV_FORMAT_PCM};
+pub use dither::Dither;
+pub use layout::ChannelLayout;
+pub use stream::{AudioReader, AudioWriter, DEFAULT_BLOCK_SIZE};
+
+// how many blocks' worth of samples `Audio::from_wav` reserves before reading
+const MAX_RESERVED_BLOCKS: usize = 256;
 
 pub struct Audio {
     pub samples: Vec<Vec<f32>>,
@@ -9,69 +23,73 @@ pub struct Audio {
 }
 
 impl Audio {
-    pub fn apply(&mut self, f: fn(&[f32]) -> Vec<f32>) {
+    pub fn apply<F: FnMut(&[f32]) -> Vec<f32>>(&mut self, mut f: F) {
         self.samples = self.samples.iter().map(|s| f(s)).collect();
     }
 
-    pub fn from_wav<R: Read + Seek>(mut stream: &mut R) -> Result<Audio, std::io::Error> {
-        let (header, data) = wav::read(&mut stream)?;
-
-        let (bit_depth, raw_samples): (u8, Result<Vec<f32>, std::io::Error>) = match data {
-            wav::BitDepth::Eight(samples) => (
-                8,
-                Ok(samples
-                    .iter()
-                    .cloned()
-                    .map(|s| s as f32 / 128.0 - 0.5)
-                    .collect()),
-            ),
-            wav::BitDepth::Sixteen(samples) => (
-                16,
-                Ok(samples
-                    .iter()
-                    .cloned()
-                    .map(|s| s as f32 / 32767.0)
-                    .collect()),
-            ),
-            wav::BitDepth::TwentyFour(samples) => (
-                24,
-                Ok(samples
-                    .iter()
-                    .cloned()
-                    // we have to divide by an extra 128 because the wav crate reads bytes in the wrong order
-                    // TODO submit a pull request to fix this
-                    .map(|s| (s as f64 / 8388607.0 / 128.0) as f32)
-                    .collect()),
-            ),
-            wav::BitDepth::ThirtyTwoFloat(samples) => (32, Ok(samples.iter().cloned().collect())),
-            wav::BitDepth::Empty => (
-                0,
-                Err(Error::new(
-                    ErrorKind::InvalidData,
-                    "Bit depth was empty; could not load samples.",
-                )),
-            ),
-        };
-        let raw_samples = raw_samples?;
+    /// Like `apply`, but `f` is also given the index of the channel it is processing.
+    pub fn apply_indexed<F: FnMut(usize, &[f32]) -> Vec<f32>>(&mut self, mut f: F) {
+        self.samples = self.samples.iter().enumerate().map(|(i, s)| f(i, s)).collect();
+    }
 
-        // cache friendly file loading; ensures that we don't have to load multiple channel sample vectors in cache at a time
-        let n_channels = header.channel_count as usize;
-        let mut samples: Vec<Vec<f32>> = Vec::with_capacity(n_channels);
-        for channel in 0..n_channels {
-            let mut channel_samples: Vec<f32> = Vec::new();
-            for &s in raw_samples.iter().skip(channel).step_by(n_channels) {
-                channel_samples.push(s);
-            }
-            samples.push(channel_samples);
+    /// Processes each channel in place, without allocating new sample vectors.
+    pub fn apply_in_place<F: FnMut(usize, &mut [f32])>(&mut self, mut f: F) {
+        self.samples.iter_mut().enumerate().for_each(|(i, s)| f(i, s));
+    }
+
+    /// Like `apply`, but processes the channels in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn par_apply<F: Fn(&[f32]) -> Vec<f32> + Sync + Send>(&mut self, f: F) {
+        use rayon::prelude::*;
+        self.samples = self.samples.par_iter().map(|s| f(s)).collect();
+    }
+
+    /// Like `apply_in_place`, but processes the channels in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn par_apply_in_place<F: Fn(usize, &mut [f32]) + Sync + Send>(&mut self, f: F) {
+        use rayon::prelude::*;
+        self.samples.par_iter_mut().enumerate().for_each(|(i, s)| f(i, s));
+    }
+
+    pub fn layout(&self) -> ChannelLayout {
+        ChannelLayout::from_header(&self.header)
+    }
+
+    pub fn set_layout(&mut self, layout: ChannelLayout) -> Result<(), std::io::Error> {
+        if layout.channel_count() as usize != self.samples.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The channel layout should have one channel per sample vector.",
+            ));
         }
+        layout.apply_to(&mut self.header);
+        Ok(())
+    }
 
-        let n_samples = samples[0].len();
-        for channel_samples in &samples {
-            if channel_samples.len() != n_samples {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    "All audio channels should have the same number of samples.",
-                ));
+    /// Converts every channel to `target_rate` samples per second and updates the header to match.
+    pub fn resample(&mut self, target_rate: u32) {
+        if target_rate == self.header.sampling_rate {
+            return;
+        }
+        let resampler = Resampler::new(self.header.sampling_rate, target_rate);
+        self.samples = self.samples.iter().map(|s| resampler.process(s)).collect();
+        self.header.sampling_rate = target_rate;
+        self.header.bytes_per_second = self.header.bytes_per_sample as u32 * target_rate;
+    }
+
+    pub fn from_wav<R: Read + Seek>(stream: &mut R) -> Result<Audio, std::io::Error> {
+        let reader = AudioReader::new(stream, DEFAULT_BLOCK_SIZE)?;
+        let header = reader.header;
+        let bit_depth = reader.bit_depth();
+
+        // the data length comes from the header, which may be corrupt or a streaming placeholder, so only part of
+        // it is reserved up front
+        let n_channels = header.channel_count as usize;
+        let capacity = reader.remaining_frames().min(MAX_RESERVED_BLOCKS * DEFAULT_BLOCK_SIZE);
+        let mut samples: Vec<Vec<f32>> = (0..n_channels).map(|_| Vec::with_capacity(capacity)).collect();
+        for block in reader {
+            for (channel_samples, block_samples) in samples.iter_mut().zip(block?) {
+                channel_samples.extend(block_samples);
             }
         }
 
@@ -83,51 +101,25 @@ impl Audio {
     }
 
     pub fn to_wav<W: Write + Seek>(&self, writer: &mut W) -> Result<(), std::io::Error> {
-        let n_samples = self.samples[0].len();
-        let n_channels = self.header.channel_count as usize;
-        let mut raw_samples: Vec<f32> = vec![0.; n_samples * self.samples.len()];
-        for (channel, channel_samples) in self.samples.iter().enumerate() {
-            (channel..raw_samples.len())
-                .step_by(n_channels)
-                .zip(channel_samples)
-                .for_each(|(i, &s)| {
-                    raw_samples[i] = s;
-                });
-        }
+        self.to_wav_dithered(writer, Dither::None, 0)
+    }
 
-        let track = match self.bit_depth {
-            8 => Ok(BitDepth::Eight(
-                raw_samples
-                    .iter()
-                    .cloned()
-                    .map(|s| ((s + 0.5) * 128.) as u8)
-                    .collect(),
-            )),
-            16 => Ok(BitDepth::Sixteen(
-                raw_samples
-                    .iter()
-                    .cloned()
-                    .map(|s| (s * 32767.0) as i16)
-                    .collect(),
-            )),
-            24 => Ok(BitDepth::TwentyFour(
-                raw_samples
-                    .iter()
-                    .cloned()
-                    // we have to multiply by an extra 128 because the wav crate reads bytes in the wrong order
-                    .map(|s| (s * 8388607. * 128.0) as i32)
-                    .collect(),
-            )),
-            32 => Ok(BitDepth::ThirtyTwoFloat(
-                raw_samples.iter().cloned().map(|s| s as f32).collect(),
-            )),
-            _ => Err(Error::new(
-                ErrorKind::InvalidData,
-                "Invalid bit depth; could not flatten samples.",
-            )),
-        }?;
-
-        wav::write(self.header, &track, writer)
+    /// Writes the audio quantized with `dither`, whose noise is generated from `seed`.
+    pub fn to_wav_dithered<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        dither: Dither,
+        seed: u64,
+    ) -> Result<(), std::io::Error> {
+        let mut writer = AudioWriter::with_dither(writer, self.header, self.bit_depth, dither, seed)?;
+        let n_samples = self.samples.first().map_or(0, |s| s.len());
+        for start in (0..n_samples).step_by(DEFAULT_BLOCK_SIZE) {
+            let end = n_samples.min(start + DEFAULT_BLOCK_SIZE);
+            let block: Vec<&[f32]> = self.samples.iter().map(|s| &s[start..end.min(s.len())]).collect();
+            writer.write_block(&block)?;
+        }
+        writer.finish()?;
+        Ok(())
     }
 }
 
@@ -135,7 +127,7 @@ impl Audio {
 mod tests {
     use std::{fs::File, path::Path};
 
-    use super::Audio;
+    use super::{Audio, Header, WAV_FORMAT_IEEE_FLOAT};
 
     #[test]
     fn test_audio_from_wav() {
@@ -150,4 +142,23 @@ mod tests {
         let audio = Audio::from_wav(&mut file).unwrap();
         audio.to_wav(&mut out_file).unwrap();
     }
+
+    #[test]
+    fn test_apply_closures() {
+        let mut audio = Audio {
+            samples: vec![vec![1., 2.], vec![3., 4.]],
+            header: Header::new(WAV_FORMAT_IEEE_FLOAT, 2, 44100, 32),
+            bit_depth: 32,
+        };
+        let gains = [2., 3.];
+        let mut calls = 0;
+        audio.apply(|s| {
+            calls += 1;
+            s.iter().map(|x| x * gains[0]).collect()
+        });
+        assert_eq!(calls, 2);
+        audio.apply_indexed(|i, s| s.iter().map(|x| x + i as f32).collect());
+        audio.apply_in_place(|i, s| s.iter_mut().for_each(|x| *x *= gains[i]));
+        assert_eq!(audio.samples, vec![vec![4., 8.], vec![21., 27.]]);
+    }
 }
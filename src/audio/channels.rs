@@ -0,0 +1,267 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::io::{Error, ErrorKind};
+
+use crate::convolution::MatrixConvolver;
+
+use super::layout::ChannelLayout;
+use super::Audio;
+
+// ITU-R BS.775 downmix gain for the center and surround channels
+const DOWNMIX_GAIN: f32 = FRAC_1_SQRT_2;
+
+fn invalid_input(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+impl Audio {
+    /// Replaces the samples and keeps the header's channel count and layout consistent with them.
+    fn set_channels(&mut self, samples: Vec<Vec<f32>>, layout: ChannelLayout) {
+        self.samples = samples;
+        // plain mono and stereo files don't need the extensible format to describe their layout
+        if self.header.extensible.is_none() && layout == ChannelLayout::unspecified(layout.channel_count()) {
+            self.header.set_channel_count(layout.channel_count());
+        } else {
+            layout.apply_to(&mut self.header);
+        }
+    }
+
+    /// Mixes the channels so that output channel `o` is the sum of each input channel `i` times `matrix[o][i]`.
+    pub fn remix(&mut self, matrix: &[Vec<f32>], layout: ChannelLayout) -> Result<(), Error> {
+        if matrix.len() != layout.channel_count() as usize {
+            return Err(invalid_input("The mixing matrix should have one row per output channel."));
+        }
+        if matrix.iter().any(|row| row.len() != self.samples.len()) {
+            return Err(invalid_input("The mixing matrix should have one column per input channel."));
+        }
+
+        let n_samples = self.samples.first().map_or(0, |s| s.len());
+        let mixed = matrix
+            .iter()
+            .map(|row| {
+                let mut out = vec![0.; n_samples];
+                for (&gain, channel) in row.iter().zip(&self.samples) {
+                    if gain != 0. {
+                        out.iter_mut().zip(channel).for_each(|(o, &s)| *o += gain * s);
+                    }
+                }
+                out
+            })
+            .collect();
+        self.set_channels(mixed, layout);
+        Ok(())
+    }
+
+    /// Like `remix`, but with a convolution from each input channel to each output channel instead of a gain. The
+    /// audio is lengthened by the convolution's tail.
+    pub fn convolve(&mut self, convolver: &mut MatrixConvolver, layout: ChannelLayout) -> Result<(), Error> {
+        if convolver.n_outputs() != layout.channel_count() as usize {
+            return Err(invalid_input("The kernel matrix should have one row per output channel."));
+        }
+        if convolver.n_inputs() != self.samples.len() {
+            return Err(invalid_input("The kernel matrix should have one column per input channel."));
+        }
+        let convolved = convolver.convolve(&self.samples);
+        self.set_channels(convolved, layout);
+        Ok(())
+    }
+
+    /// Downmixes to mono or stereo, using the standard coefficients for 5.1 and 7.1 sources.
+    pub fn downmix(&mut self, layout: ChannelLayout) -> Result<(), Error> {
+        let k = DOWNMIX_GAIN;
+        let matrix = match (self.layout(), layout) {
+            (from, to) if from == to => return Ok(()),
+            (ChannelLayout::Surround51, ChannelLayout::Stereo) => vec![
+                vec![1., 0., k, 0., k, 0.],
+                vec![0., 1., k, 0., 0., k],
+            ],
+            (ChannelLayout::Surround71, ChannelLayout::Stereo) => vec![
+                vec![1., 0., k, 0., k, 0., k, 0.],
+                vec![0., 1., k, 0., 0., k, 0., k],
+            ],
+            (ChannelLayout::Surround51, ChannelLayout::Mono) | (ChannelLayout::Surround71, ChannelLayout::Mono) => {
+                self.downmix(ChannelLayout::Stereo)?;
+                return self.downmix(ChannelLayout::Mono);
+            }
+            (ChannelLayout::Foa, _) => {
+                return Err(invalid_input("Ambisonic audio has to be decoded rather than downmixed."))
+            }
+            (_, ChannelLayout::Mono) => {
+                let n_channels = self.samples.len();
+                vec![vec![1. / n_channels as f32; n_channels]]
+            }
+            _ => return Err(invalid_input("Audio can only be downmixed to mono or stereo.")),
+        };
+        self.remix(&matrix, layout)
+    }
+
+    /// Upmixes mono or stereo audio; mono is copied to both sides of stereo or to the center of surround
+    /// layouts, and the extra surround channels are left silent.
+    pub fn upmix(&mut self, layout: ChannelLayout) -> Result<(), Error> {
+        let n_out = layout.channel_count() as usize;
+        let mut matrix = vec![vec![0.; self.samples.len()]; n_out];
+        match (self.layout(), layout) {
+            (from, to) if from == to => return Ok(()),
+            (ChannelLayout::Mono, ChannelLayout::Stereo) => {
+                matrix[0][0] = 1.;
+                matrix[1][0] = 1.;
+            }
+            (ChannelLayout::Mono, ChannelLayout::Surround51) | (ChannelLayout::Mono, ChannelLayout::Surround71) => {
+                matrix[2][0] = 1.;
+            }
+            (ChannelLayout::Stereo, ChannelLayout::Surround51) | (ChannelLayout::Stereo, ChannelLayout::Surround71) => {
+                matrix[0][0] = 1.;
+                matrix[1][1] = 1.;
+            }
+            _ => return Err(invalid_input("Only mono and stereo audio can be upmixed to a speaker layout.")),
+        }
+        self.remix(&matrix, layout)
+    }
+
+    /// Copies one channel into a new mono `Audio` with the same format.
+    pub fn extract_channel(&self, channel: usize) -> Result<Audio, Error> {
+        let samples = self
+            .samples
+            .get(channel)
+            .ok_or_else(|| invalid_input("Channel index out of range."))?;
+        let mut header = self.header;
+        header.extensible = None;
+        header.set_channel_count(1);
+        Ok(Audio {
+            samples: vec![samples.clone()],
+            header,
+            bit_depth: self.bit_depth,
+        })
+    }
+
+    pub fn split_channels(&self) -> Vec<Audio> {
+        (0..self.samples.len())
+            .filter_map(|channel| self.extract_channel(channel).ok())
+            .collect()
+    }
+
+    /// Inserts a channel before `index`; the layout is reset since the speaker positions are no longer known.
+    pub fn insert_channel(&mut self, index: usize, samples: Vec<f32>) -> Result<(), Error> {
+        if index > self.samples.len() {
+            return Err(invalid_input("Channel index out of range."));
+        }
+        if self.samples.first().is_some_and(|s| s.len() != samples.len()) {
+            return Err(invalid_input("All audio channels should have the same number of samples."));
+        }
+        let mut channels = std::mem::take(&mut self.samples);
+        channels.insert(index, samples);
+        let layout = ChannelLayout::unspecified(channels.len() as u16);
+        self.set_channels(channels, layout);
+        Ok(())
+    }
+
+    pub fn remove_channel(&mut self, index: usize) -> Result<Vec<f32>, Error> {
+        if index >= self.samples.len() || self.samples.len() == 1 {
+            return Err(invalid_input("Channel index out of range, or the only channel."));
+        }
+        let mut channels = std::mem::take(&mut self.samples);
+        let removed = channels.remove(index);
+        let layout = ChannelLayout::unspecified(channels.len() as u16);
+        self.set_channels(channels, layout);
+        Ok(removed)
+    }
+
+    /// Encodes stereo left/right into mid (L + R) / 2 and side (L - R) / 2, in place of the left and right channels.
+    pub fn to_mid_side(&mut self) -> Result<(), Error> {
+        self.mid_side_transform(0.5)
+    }
+
+    /// Decodes mid/side channels back into left (M + S) and right (M - S).
+    pub fn from_mid_side(&mut self) -> Result<(), Error> {
+        self.mid_side_transform(1.)
+    }
+
+    fn mid_side_transform(&mut self, gain: f32) -> Result<(), Error> {
+        if self.samples.len() != 2 {
+            return Err(invalid_input("Mid/side coding needs exactly two channels."));
+        }
+        let (a, b) = self.samples.split_at_mut(1);
+        for (x, y) in a[0].iter_mut().zip(b[0].iter_mut()) {
+            let (sum, difference) = (*x + *y, *x - *y);
+            *x = sum * gain;
+            *y = difference * gain;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use crate::audio::codec::{Header, WAV_FORMAT_IEEE_FLOAT};
+    use crate::convolution::MatrixConvolver;
+    use crate::audio::layout::ChannelLayout;
+    use crate::audio::Audio;
+
+    fn audio(samples: Vec<Vec<f32>>) -> Audio {
+        let header = Header::new(WAV_FORMAT_IEEE_FLOAT, samples.len() as u16, 48000, 32);
+        Audio {
+            samples,
+            header,
+            bit_depth: 32,
+        }
+    }
+
+    #[test]
+    fn test_downmix_51_to_stereo_and_mono() {
+        let mut a = audio(vec![vec![1.], vec![0.5], vec![1.], vec![1.], vec![1.], vec![0.]]);
+        a.set_layout(ChannelLayout::Surround51).unwrap();
+        a.downmix(ChannelLayout::Stereo).unwrap();
+        assert_eq!(a.header.channel_count, 2);
+        assert!((a.samples[0][0] - (1. + 2. * FRAC_1_SQRT_2)).abs() < 1e-6);
+        assert!((a.samples[1][0] - (0.5 + FRAC_1_SQRT_2)).abs() < 1e-6);
+
+        a.downmix(ChannelLayout::Mono).unwrap();
+        assert_eq!(a.header.channel_count, 1);
+        assert_eq!(a.header.bytes_per_sample, 4);
+        assert_eq!(a.layout(), ChannelLayout::Mono);
+    }
+
+    #[test]
+    fn test_convolve_mono_to_stereo() {
+        let mut a = audio(vec![vec![1., 2.]]);
+        let mut convolver = MatrixConvolver::new(vec![vec![vec![1., 0.5]], vec![vec![0., 0., -1.]]]);
+        assert!(a.convolve(&mut convolver, ChannelLayout::Mono).is_err());
+        a.convolve(&mut convolver, ChannelLayout::Stereo).unwrap();
+        assert_eq!(a.header.channel_count, 2);
+        let expected = vec![vec![1., 2.5, 1., 0.], vec![0., 0., -1., -2.]];
+        for (channel, expected) in a.samples.iter().zip(&expected) {
+            assert!(channel.iter().zip(expected).all(|(x, e)| (x - e).abs() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn test_upmix_mono() {
+        let mut a = audio(vec![vec![0.25, -0.5]]);
+        a.upmix(ChannelLayout::Stereo).unwrap();
+        assert_eq!(a.samples, vec![vec![0.25, -0.5], vec![0.25, -0.5]]);
+        assert_eq!(a.header.channel_count, 2);
+        assert!(a.upmix(ChannelLayout::Foa).is_err());
+    }
+
+    #[test]
+    fn test_insert_extract_remove() {
+        let mut a = audio(vec![vec![1., 2.], vec![3., 4.]]);
+        a.insert_channel(1, vec![5., 6.]).unwrap();
+        assert_eq!(a.header.channel_count, 3);
+        assert_eq!(a.extract_channel(1).unwrap().samples, vec![vec![5., 6.]]);
+        assert!(a.insert_channel(0, vec![1.]).is_err());
+        assert_eq!(a.remove_channel(0).unwrap(), vec![1., 2.]);
+        assert_eq!(a.header.channel_count, 2);
+        assert_eq!(a.layout(), ChannelLayout::Stereo);
+    }
+
+    #[test]
+    fn test_mid_side_round_trip() {
+        let mut a = audio(vec![vec![1., 0.5], vec![0., 0.25]]);
+        a.to_mid_side().unwrap();
+        assert_eq!(a.samples, vec![vec![0.5, 0.375], vec![0.5, 0.125]]);
+        a.from_mid_side().unwrap();
+        assert_eq!(a.samples, vec![vec![1., 0.5], vec![0., 0.25]]);
+    }
+}
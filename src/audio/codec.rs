@@ -0,0 +1,401 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+pub const WAV_FORMAT_PCM: u16 = 0x01;
+pub const WAV_FORMAT_IEEE_FLOAT: u16 = 0x03;
+pub const WAV_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// the sub format GUIDs share a common tail after the two bytes holding the format code
+const KSDATAFORMAT_GUID_TAIL: [u8; 14] = [0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71];
+const AMBISONIC_B_FORMAT_GUID_TAIL: [u8; 14] = [0, 0, 0x21, 0x07, 0xD3, 0x11, 0x86, 0x44, 0xC8, 0xC1, 0xCA, 0, 0, 0];
+
+/// The extra fields of a `WAVE_FORMAT_EXTENSIBLE` fmt chunk.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Extensible {
+    pub valid_bits_per_sample: u16,
+    pub channel_mask: u32,
+    /// Whether the sub format is ambisonic B-format rather than plain PCM or float.
+    pub ambisonic: bool,
+}
+
+/// The contents of a wav file's `fmt ` chunk. For extensible files, `audio_format` holds the sub format's code.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub audio_format: u16,
+    pub channel_count: u16,
+    pub sampling_rate: u32,
+    pub bytes_per_second: u32,
+    pub bytes_per_sample: u16,
+    pub bits_per_sample: u16,
+    pub extensible: Option<Extensible>,
+}
+
+impl Header {
+    pub fn new(audio_format: u16, channel_count: u16, sampling_rate: u32, bits_per_sample: u16) -> Header {
+        let bytes_per_sample = (bits_per_sample / 8) * channel_count;
+        Header {
+            audio_format,
+            channel_count,
+            sampling_rate,
+            bytes_per_second: bytes_per_sample as u32 * sampling_rate,
+            bytes_per_sample,
+            bits_per_sample,
+            extensible: None,
+        }
+    }
+
+    /// Sets the channel count along with the frame size and byte rate which depend on it.
+    pub fn set_channel_count(&mut self, channel_count: u16) {
+        self.channel_count = channel_count;
+        self.bytes_per_sample = (self.bits_per_sample / 8) * channel_count;
+        self.bytes_per_second = self.bytes_per_sample as u32 * self.sampling_rate;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Header, Error> {
+        if bytes.len() < 16 {
+            return Err(Error::new(ErrorKind::InvalidData, "The fmt chunk should be at least 16 bytes long."));
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let mut header = Header {
+            audio_format: u16_at(0),
+            channel_count: u16_at(2),
+            sampling_rate: u32_at(4),
+            bytes_per_second: u32_at(8),
+            bytes_per_sample: u16_at(12),
+            bits_per_sample: u16_at(14),
+            extensible: None,
+        };
+
+        if header.audio_format == WAV_FORMAT_EXTENSIBLE {
+            if bytes.len() < 40 || u16_at(16) < 22 {
+                return Err(Error::new(ErrorKind::InvalidData, "The extensible fmt chunk should be 40 bytes long."));
+            }
+            let ambisonic = if bytes[26..40] == KSDATAFORMAT_GUID_TAIL {
+                false
+            } else if bytes[26..40] == AMBISONIC_B_FORMAT_GUID_TAIL {
+                true
+            } else {
+                return Err(Error::new(ErrorKind::InvalidData, "Unsupported extensible sub format."));
+            };
+            header.audio_format = u16_at(24);
+            header.extensible = Some(Extensible {
+                valid_bits_per_sample: u16_at(18),
+                channel_mask: u32_at(20),
+                ambisonic,
+            });
+        }
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(40);
+        let audio_format = match self.extensible {
+            Some(_) => WAV_FORMAT_EXTENSIBLE,
+            None => self.audio_format,
+        };
+        bytes.extend(&audio_format.to_le_bytes());
+        bytes.extend(&self.channel_count.to_le_bytes());
+        bytes.extend(&self.sampling_rate.to_le_bytes());
+        bytes.extend(&self.bytes_per_second.to_le_bytes());
+        bytes.extend(&self.bytes_per_sample.to_le_bytes());
+        bytes.extend(&self.bits_per_sample.to_le_bytes());
+        if let Some(extensible) = self.extensible {
+            bytes.extend(&22u16.to_le_bytes());
+            bytes.extend(&extensible.valid_bits_per_sample.to_le_bytes());
+            bytes.extend(&extensible.channel_mask.to_le_bytes());
+            bytes.extend(&self.audio_format.to_le_bytes());
+            if extensible.ambisonic {
+                bytes.extend(&AMBISONIC_B_FORMAT_GUID_TAIL);
+            } else {
+                bytes.extend(&KSDATAFORMAT_GUID_TAIL);
+            }
+        }
+        bytes
+    }
+}
+
+/// The encodings a sample can have in the data chunk of a wav file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    /// 32 bit integers, which are decoded to f32 and so keep only its 24 bits of precision: full scale samples
+    /// can move by up to 64 steps in a round trip.
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn from_header(header: &Header) -> Result<SampleFormat, Error> {
+        match (header.audio_format, header.bits_per_sample) {
+            (WAV_FORMAT_PCM, 8) => Ok(SampleFormat::U8),
+            (WAV_FORMAT_PCM, 16) => Ok(SampleFormat::I16),
+            (WAV_FORMAT_PCM, 24) => Ok(SampleFormat::I24),
+            (WAV_FORMAT_PCM, 32) => Ok(SampleFormat::I32),
+            (WAV_FORMAT_IEEE_FLOAT, 32) => Ok(SampleFormat::F32),
+            (WAV_FORMAT_IEEE_FLOAT, 64) => Ok(SampleFormat::F64),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                "Unsupported audio format or bit depth; could not load samples.",
+            )),
+        }
+    }
+
+    pub fn bit_depth(self) -> u8 {
+        match self {
+            SampleFormat::U8 => 8,
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    pub fn bytes(self) -> usize {
+        self.bit_depth() as usize / 8
+    }
+
+    /// The number of quantization steps per unit of amplitude, or `None` for float formats.
+    pub fn scale(self) -> Option<f64> {
+        match self {
+            SampleFormat::U8 => Some(128.0),
+            SampleFormat::I16 => Some(32768.0),
+            SampleFormat::I24 => Some(8388608.0),
+            SampleFormat::I32 => Some(2147483648.0),
+            SampleFormat::F32 | SampleFormat::F64 => None,
+        }
+    }
+
+    /// Decodes one little-endian sample into the range [-1, 1).
+    pub fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            // 8 bit samples are unsigned, with silence at 128
+            SampleFormat::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+            SampleFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            // shift into the top of an i32 so that the sign is extended when shifting back down
+            SampleFormat::I24 => (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / 8388608.0,
+            // rounded to the nearest f32, which is coarser than the integer steps above 2^24
+            SampleFormat::I32 => {
+                (i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64 / 2147483648.0) as f32
+            }
+            SampleFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            SampleFormat::F64 => {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(&bytes[..8]);
+                f64::from_le_bytes(b) as f32
+            }
+        }
+    }
+
+    /// Encodes one sample as little-endian bytes; integer formats are rounded and saturate at full scale.
+    pub fn encode(self, sample: f32, bytes: &mut [u8]) {
+        let quantize = |scale: f64, min: f64, max: f64| (sample as f64 * scale).round().max(min).min(max);
+        match self {
+            SampleFormat::U8 => bytes[0] = (quantize(128.0, -128.0, 127.0) + 128.0) as u8,
+            SampleFormat::I16 => {
+                bytes[..2].copy_from_slice(&(quantize(32768.0, -32768.0, 32767.0) as i16).to_le_bytes())
+            }
+            SampleFormat::I24 => {
+                let s = quantize(8388608.0, -8388608.0, 8388607.0) as i32;
+                bytes[..3].copy_from_slice(&s.to_le_bytes()[..3]);
+            }
+            SampleFormat::I32 => {
+                let s = quantize(2147483648.0, -2147483648.0, 2147483647.0) as i32;
+                bytes[..4].copy_from_slice(&s.to_le_bytes());
+            }
+            SampleFormat::F32 => bytes[..4].copy_from_slice(&sample.to_le_bytes()),
+            SampleFormat::F64 => bytes[..8].copy_from_slice(&(sample as f64).to_le_bytes()),
+        }
+    }
+}
+
+/// Walks the chunks of a RIFF/WAVE stream up to the start of the sample data, returning the header and the
+/// length of the data chunk in bytes.
+pub fn read_header<R: Read + Seek>(reader: &mut R) -> Result<(Header, usize), Error> {
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(Error::new(ErrorKind::InvalidData, "Stream is not a RIFF/WAVE file."));
+    }
+
+    let mut header = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        // chunks are always padded to an even number of bytes
+        let padded_len = len + len % 2;
+        match (&chunk[0..4], header) {
+            (b"fmt ", _) => {
+                let mut contents = vec![0u8; padded_len];
+                reader.read_exact(&mut contents)?;
+                header = Some(Header::from_bytes(&contents)?);
+            }
+            (b"data", Some(header)) => return Ok((header, len)),
+            (b"data", None) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Found a data chunk before the fmt chunk.",
+                ))
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(padded_len as i64))?;
+            }
+        }
+    }
+}
+
+/// Writes the RIFF, fmt and data chunk headers with placeholder sizes which are filled in by `finish_data`,
+/// returning the number of bytes written.
+pub fn write_header<W: Write>(writer: &mut W, header: &Header) -> Result<u64, Error> {
+    let fmt = header.to_bytes();
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&(fmt.len() as u32).to_le_bytes())?;
+    writer.write_all(&fmt)?;
+    writer.write_all(b"data")?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    Ok(28 + fmt.len() as u64)
+}
+
+/// Pads the data chunk and fills in the chunk sizes of a file written from `start` by `write_header`, where
+/// `data_start` is the value `write_header` returned.
+pub fn finish_data<W: Write + Seek>(writer: &mut W, start: u64, data_start: u64, data_len: u64) -> Result<(), Error> {
+    let too_long = || Error::new(ErrorKind::InvalidData, "Audio is too long for a wav file.");
+    if data_len % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    let end = writer.stream_position()?;
+    let riff_len = u32::try_from(end - start - 8).map_err(|_| too_long())?;
+    let data_len = u32::try_from(data_len).map_err(|_| too_long())?;
+
+    writer.seek(SeekFrom::Start(start + 4))?;
+    writer.write_all(&riff_len.to_le_bytes())?;
+    // the data chunk size is the last field written before the samples
+    writer.seek(SeekFrom::Start(start + data_start - 4))?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::audio::Audio;
+
+    use super::{SampleFormat, WAV_FORMAT_IEEE_FLOAT, WAV_FORMAT_PCM};
+
+    fn fixture(audio_format: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut bytes = Vec::new();
+        bytes.extend(b"RIFF");
+        bytes.extend(&(36 + data.len() as u32 + data.len() as u32 % 2).to_le_bytes());
+        bytes.extend(b"WAVE");
+        bytes.extend(b"fmt ");
+        bytes.extend(&16u32.to_le_bytes());
+        bytes.extend(&audio_format.to_le_bytes());
+        bytes.extend(&channels.to_le_bytes());
+        bytes.extend(&8000u32.to_le_bytes());
+        bytes.extend(&(8000 * block_align as u32).to_le_bytes());
+        bytes.extend(&block_align.to_le_bytes());
+        bytes.extend(&bits.to_le_bytes());
+        bytes.extend(b"data");
+        bytes.extend(&(data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        if data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn assert_round_trip(bytes: &[u8], expected: &[Vec<f32>]) {
+        let audio = Audio::from_wav(&mut Cursor::new(bytes.to_vec())).unwrap();
+        assert_eq!(audio.samples, expected);
+        let mut written = Cursor::new(Vec::new());
+        audio.to_wav(&mut written).unwrap();
+        assert_eq!(written.into_inner(), bytes);
+    }
+
+    #[test]
+    fn test_u8_round_trip() {
+        let bytes = fixture(WAV_FORMAT_PCM, 1, 8, &[0x00, 0x40, 0x80, 0xC0, 0xFF]);
+        assert_round_trip(&bytes, &[vec![-1.0, -0.5, 0.0, 0.5, 127.0 / 128.0]]);
+    }
+
+    #[test]
+    fn test_i16_round_trip() {
+        let bytes = fixture(WAV_FORMAT_PCM, 2, 16, &[0x00, 0x80, 0xFF, 0x7F, 0x00, 0x40, 0x01, 0x00]);
+        assert_round_trip(&bytes, &[vec![-1.0, 0.5], vec![32767.0 / 32768.0, 1.0 / 32768.0]]);
+    }
+
+    #[test]
+    fn test_i24_round_trip() {
+        let bytes = fixture(
+            WAV_FORMAT_PCM,
+            1,
+            24,
+            &[0x00, 0x00, 0x80, 0xFF, 0xFF, 0x7F, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xC0],
+        );
+        assert_round_trip(&bytes, &[vec![-1.0, 8388607.0 / 8388608.0, -1.0 / 8388608.0, -0.5]]);
+    }
+
+    #[test]
+    fn test_i32_round_trip() {
+        let bytes = fixture(WAV_FORMAT_PCM, 1, 32, &[0x00, 0x00, 0x00, 0x80, 0x00, 0x01, 0x00, 0x00]);
+        assert_round_trip(&bytes, &[vec![-1.0, 256.0 / 2147483648.0]]);
+
+        // near full scale, f32 steps are 128 integer steps apart, so a round trip is off by up to half of that
+        let near_full_scale = [i32::MAX, i32::MAX - 64, i32::MAX - 65, (1 << 30) + 1, i32::MIN, i32::MIN + 191];
+        for &x in &near_full_scale {
+            let decoded = SampleFormat::I32.decode(&x.to_le_bytes());
+            assert!((decoded as f64 * 2147483648.0 - x as f64).abs() <= 64.);
+            let mut bytes = [0u8; 4];
+            SampleFormat::I32.encode(decoded, &mut bytes);
+            assert!((i32::from_le_bytes(bytes) as i64 - x as i64).abs() <= 64);
+        }
+    }
+
+    #[test]
+    fn test_f32_round_trip() {
+        let mut data = Vec::new();
+        data.extend(&0.25f32.to_le_bytes());
+        data.extend(&(-1.5f32).to_le_bytes());
+        let bytes = fixture(WAV_FORMAT_IEEE_FLOAT, 1, 32, &data);
+        assert_round_trip(&bytes, &[vec![0.25, -1.5]]);
+    }
+
+    #[test]
+    fn test_f64_round_trip() {
+        let mut data = Vec::new();
+        data.extend(&(-0.125f64).to_le_bytes());
+        data.extend(&0.75f64.to_le_bytes());
+        let bytes = fixture(WAV_FORMAT_IEEE_FLOAT, 1, 64, &data);
+        assert_round_trip(&bytes, &[vec![-0.125, 0.75]]);
+    }
+
+    #[test]
+    fn test_oversized_data_length() {
+        // a data length left at its maximum, as streaming writers do, runs out of data instead of reserving
+        // gigabytes up front
+        let mut bytes = fixture(WAV_FORMAT_PCM, 2, 16, &[0x00, 0x80, 0xFF, 0x7F]);
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Audio::from_wav(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_encode_saturates() {
+        let mut bytes = [0u8; 3];
+        SampleFormat::I24.encode(1.5, &mut bytes);
+        assert_eq!(bytes, [0xFF, 0xFF, 0x7F]);
+        SampleFormat::I24.encode(-1.5, &mut bytes);
+        assert_eq!(bytes, [0x00, 0x00, 0x80]);
+        SampleFormat::U8.encode(1.0, &mut bytes[..1]);
+        assert_eq!(bytes[0], 0xFF);
+    }
+}
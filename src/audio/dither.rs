@@ -0,0 +1,120 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::noise::white_noise;
+
+use super::codec::SampleFormat;
+
+// error feedback filter which pushes quantization noise above the ear's most sensitive band (Lipshitz et al.)
+const SHAPING_COEFFICIENTS: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+/// How samples are dithered when they are quantized to an integer bit depth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the nearest step; quantization error is correlated with the signal.
+    None,
+    /// Add triangular noise of 2 steps peak to peak, which decorrelates the error from the signal.
+    Tpdf,
+    /// Tpdf dither, with the quantization error fed back so that its spectrum is shaped towards high frequencies.
+    NoiseShaped,
+}
+
+/// Per-channel dither state for quantizing a stream of blocks. The dither noise comes from a generator seeded
+/// with `seed`, so that the same input is always quantized the same way.
+pub struct Ditherer {
+    dither: Dither,
+    format: SampleFormat,
+    errors: Vec<[f64; 5]>,
+    rng: StdRng,
+}
+
+impl Ditherer {
+    pub fn new(dither: Dither, format: SampleFormat, n_channels: usize, seed: u64) -> Ditherer {
+        Ditherer {
+            dither,
+            format,
+            errors: vec![[0.; 5]; n_channels],
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Quantizes `samples` in place to the steps of the output format, saturating at full scale. Float formats
+    /// are left unchanged.
+    pub fn process(&mut self, channel: usize, samples: &mut [f32]) {
+        let scale = match (self.dither, self.format.scale()) {
+            (Dither::None, _) | (_, None) => return,
+            (_, Some(scale)) => scale,
+        };
+        let (min, max) = (-scale, scale - 1.);
+
+        // the difference of two uniform values has a triangular distribution
+        let a = white_noise(samples.len(), &mut self.rng);
+        let b = white_noise(samples.len(), &mut self.rng);
+        let errors = &mut self.errors[channel];
+        let shaped = self.dither == Dither::NoiseShaped;
+
+        for ((s, &a), &b) in samples.iter_mut().zip(&a).zip(&b) {
+            let mut target = *s as f64 * scale;
+            if shaped {
+                target -= SHAPING_COEFFICIENTS
+                    .iter()
+                    .zip(errors.iter())
+                    .map(|(c, e)| c * e)
+                    .sum::<f64>();
+            }
+            let quantized = (target + (a - b) as f64 * 0.5).round();
+            if shaped {
+                errors.rotate_right(1);
+                // clip the fed back error so that overloads can't make the filter unstable
+                errors[0] = (quantized - target).clamp(-1.5, 1.5);
+            }
+            *s = (quantized.clamp(min, max) / scale) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::codec::SampleFormat;
+
+    use super::{Dither, Ditherer};
+
+    #[test]
+    fn test_dither_quantizes_and_saturates() {
+        for &dither in &[Dither::Tpdf, Dither::NoiseShaped] {
+            let mut ditherer = Ditherer::new(dither, SampleFormat::I16, 1, 0);
+            let mut samples: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.05).sin() * 1.5).collect();
+            ditherer.process(0, &mut samples);
+            assert!(samples.iter().all(|&s| (-1.0..1.0).contains(&s)));
+            assert!(samples.iter().all(|&s| (s * 32768.0).fract() == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_tpdf_dither_decorrelates_silence() {
+        // a constant signal half a step above zero would always round the same way without dither
+        let mut ditherer = Ditherer::new(Dither::Tpdf, SampleFormat::I16, 1, 0);
+        let mut samples = vec![0.5 / 32768.0; 10000];
+        ditherer.process(0, &mut samples);
+        let mean = samples.iter().map(|&s| s as f64 * 32768.0).sum::<f64>() / samples.len() as f64;
+        assert!((mean - 0.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_dither_is_seeded() {
+        let dithered = |seed: u64| {
+            let mut samples: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+            Ditherer::new(Dither::NoiseShaped, SampleFormat::I16, 1, seed).process(0, &mut samples);
+            samples
+        };
+        assert_eq!(dithered(3), dithered(3));
+        assert_ne!(dithered(3), dithered(4));
+    }
+
+    #[test]
+    fn test_float_formats_are_untouched() {
+        let mut ditherer = Ditherer::new(Dither::NoiseShaped, SampleFormat::F32, 1, 0);
+        let mut samples = vec![0.123, -0.456];
+        ditherer.process(0, &mut samples);
+        assert_eq!(samples, vec![0.123, -0.456]);
+    }
+}
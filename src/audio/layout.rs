@@ -0,0 +1,177 @@
+use super::codec::{Extensible, Header};
+
+pub const SPEAKER_FRONT_LEFT: u32 = 0x1;
+pub const SPEAKER_FRONT_RIGHT: u32 = 0x2;
+pub const SPEAKER_FRONT_CENTER: u32 = 0x4;
+pub const SPEAKER_LOW_FREQUENCY: u32 = 0x8;
+pub const SPEAKER_BACK_LEFT: u32 = 0x10;
+pub const SPEAKER_BACK_RIGHT: u32 = 0x20;
+pub const SPEAKER_SIDE_LEFT: u32 = 0x200;
+pub const SPEAKER_SIDE_RIGHT: u32 = 0x400;
+
+const MONO_MASK: u32 = SPEAKER_FRONT_CENTER;
+const STEREO_MASK: u32 = SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT;
+const SURROUND_51_MASK: u32 =
+    STEREO_MASK | SPEAKER_FRONT_CENTER | SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT;
+const SURROUND_71_MASK: u32 = SURROUND_51_MASK | SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT;
+
+/// The speaker arrangement of an audio stream's channels. Channels are ordered as in their wav channel mask:
+/// 5.1 is L, R, C, LFE, Ls, Rs and 7.1 is L, R, C, LFE, Lb, Rb, Ls, Rs. First order ambisonics (FOA) is
+/// B-format W, X, Y, Z.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    Surround51,
+    Surround71,
+    Foa,
+    Other { channel_count: u16, channel_mask: u32 },
+}
+
+impl ChannelLayout {
+    pub fn from_header(header: &Header) -> ChannelLayout {
+        match header.extensible {
+            Some(Extensible { ambisonic: true, .. }) if header.channel_count == 4 => ChannelLayout::Foa,
+            Some(Extensible { ambisonic: false, channel_mask, .. }) => {
+                ChannelLayout::from_mask(header.channel_count, channel_mask)
+            }
+            Some(Extensible { channel_mask, .. }) => ChannelLayout::Other {
+                channel_count: header.channel_count,
+                channel_mask,
+            },
+            None => ChannelLayout::unspecified(header.channel_count),
+        }
+    }
+
+    /// The layout of a stream with no channel mask, where only mono and stereo are implied by the channel count.
+    pub fn unspecified(channel_count: u16) -> ChannelLayout {
+        match channel_count {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            channel_count => ChannelLayout::Other {
+                channel_count,
+                channel_mask: 0,
+            },
+        }
+    }
+
+    fn from_mask(channel_count: u16, channel_mask: u32) -> ChannelLayout {
+        match (channel_count, channel_mask) {
+            (1, MONO_MASK) => ChannelLayout::Mono,
+            (2, STEREO_MASK) => ChannelLayout::Stereo,
+            (6, SURROUND_51_MASK) => ChannelLayout::Surround51,
+            (8, SURROUND_71_MASK) => ChannelLayout::Surround71,
+            _ => ChannelLayout::Other {
+                channel_count,
+                channel_mask,
+            },
+        }
+    }
+
+    pub fn channel_count(&self) -> u16 {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Surround51 => 6,
+            ChannelLayout::Surround71 => 8,
+            ChannelLayout::Foa => 4,
+            ChannelLayout::Other { channel_count, .. } => *channel_count,
+        }
+    }
+
+    /// The wav speaker mask; ambisonic layouts have no speakers, so their mask is 0.
+    pub fn channel_mask(&self) -> u32 {
+        match self {
+            ChannelLayout::Mono => MONO_MASK,
+            ChannelLayout::Stereo => STEREO_MASK,
+            ChannelLayout::Surround51 => SURROUND_51_MASK,
+            ChannelLayout::Surround71 => SURROUND_71_MASK,
+            ChannelLayout::Foa => 0,
+            ChannelLayout::Other { channel_mask, .. } => *channel_mask,
+        }
+    }
+
+    /// Sets the channel count and writes the layout into `header` as a `WAVE_FORMAT_EXTENSIBLE` fmt chunk.
+    pub fn apply_to(&self, header: &mut Header) {
+        header.set_channel_count(self.channel_count());
+        header.extensible = Some(Extensible {
+            valid_bits_per_sample: header.bits_per_sample,
+            channel_mask: self.channel_mask(),
+            ambisonic: *self == ChannelLayout::Foa,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::audio::codec::{Header, WAV_FORMAT_PCM};
+    use crate::audio::Audio;
+
+    use super::ChannelLayout;
+
+    fn extensible_fixture(channels: u16, channel_mask: u32, guid_tail: &[u8]) -> Vec<u8> {
+        let block_align = channels * 2;
+        let mut bytes = Vec::new();
+        bytes.extend(b"RIFF");
+        bytes.extend(&(60 + block_align as u32).to_le_bytes());
+        bytes.extend(b"WAVE");
+        bytes.extend(b"fmt ");
+        bytes.extend(&40u32.to_le_bytes());
+        bytes.extend(&0xFFFEu16.to_le_bytes());
+        bytes.extend(&channels.to_le_bytes());
+        bytes.extend(&48000u32.to_le_bytes());
+        bytes.extend(&(48000 * block_align as u32).to_le_bytes());
+        bytes.extend(&block_align.to_le_bytes());
+        bytes.extend(&16u16.to_le_bytes());
+        bytes.extend(&22u16.to_le_bytes());
+        bytes.extend(&16u16.to_le_bytes());
+        bytes.extend(&channel_mask.to_le_bytes());
+        bytes.extend(&1u16.to_le_bytes());
+        bytes.extend(guid_tail);
+        bytes.extend(b"data");
+        bytes.extend(&(block_align as u32).to_le_bytes());
+        bytes.extend(vec![0u8; block_align as usize]);
+        bytes
+    }
+
+    fn assert_layout_round_trip(bytes: Vec<u8>, layout: ChannelLayout) {
+        let audio = Audio::from_wav(&mut Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(audio.layout(), layout);
+        let mut written = Cursor::new(Vec::new());
+        audio.to_wav(&mut written).unwrap();
+        assert_eq!(written.into_inner(), bytes);
+    }
+
+    #[test]
+    fn test_surround_51_round_trip() {
+        let tail = [0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71];
+        assert_layout_round_trip(extensible_fixture(6, 0x3F, &tail), ChannelLayout::Surround51);
+    }
+
+    #[test]
+    fn test_foa_round_trip() {
+        let tail = [0, 0, 0x21, 0x07, 0xD3, 0x11, 0x86, 0x44, 0xC8, 0xC1, 0xCA, 0, 0, 0];
+        assert_layout_round_trip(extensible_fixture(4, 0, &tail), ChannelLayout::Foa);
+    }
+
+    #[test]
+    fn test_unknown_mask_is_preserved() {
+        let tail = [0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71];
+        let layout = ChannelLayout::Other {
+            channel_count: 6,
+            channel_mask: 0x60F,
+        };
+        assert_layout_round_trip(extensible_fixture(6, 0x60F, &tail), layout);
+    }
+
+    #[test]
+    fn test_apply_layout() {
+        let mut header = Header::new(WAV_FORMAT_PCM, 2, 48000, 24);
+        ChannelLayout::Surround71.apply_to(&mut header);
+        assert_eq!(header.channel_count, 8);
+        assert_eq!(header.bytes_per_sample, 24);
+        assert_eq!(ChannelLayout::from_header(&header), ChannelLayout::Surround71);
+    }
+}
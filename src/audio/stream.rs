@@ -0,0 +1,217 @@
+use std::io::{Error, ErrorKind, Read, Seek, Write};
+
+use super::codec::{finish_data, read_header, write_header, Header, SampleFormat};
+use super::dither::{Dither, Ditherer};
+
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Reads a wav file in blocks of de-interleaved samples, without loading the whole file into memory.
+pub struct AudioReader<R: Read + Seek> {
+    reader: R,
+    pub header: Header,
+    pub format: SampleFormat,
+    block_size: usize,
+    remaining_frames: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read + Seek> AudioReader<R> {
+    pub fn new(mut reader: R, block_size: usize) -> Result<AudioReader<R>, Error> {
+        if block_size == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Block size must be nonzero."));
+        }
+
+        let (header, data_len) = read_header(&mut reader)?;
+        let format = SampleFormat::from_header(&header)?;
+        let frame_len = header.channel_count as usize * format.bytes();
+        if frame_len == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Audio has no channels."));
+        }
+        if data_len % frame_len != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "All audio channels should have the same number of samples.",
+            ));
+        }
+
+        Ok(AudioReader {
+            reader,
+            header,
+            format,
+            block_size,
+            remaining_frames: data_len / frame_len,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.format.bit_depth()
+    }
+
+    /// The number of frames (samples per channel) which have not been read yet.
+    pub fn remaining_frames(&self) -> usize {
+        self.remaining_frames
+    }
+
+    /// Reads the next block of at most `block_size` samples per channel, or `None` at the end of the data.
+    pub fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, Error> {
+        let n_frames = self.block_size.min(self.remaining_frames);
+        if n_frames == 0 {
+            return Ok(None);
+        }
+
+        let n_channels = self.header.channel_count as usize;
+        let sample_len = self.format.bytes();
+        self.buffer.resize(n_frames * n_channels * sample_len, 0);
+        self.reader.read_exact(&mut self.buffer)?;
+        self.remaining_frames -= n_frames;
+
+        // de-interleave a channel at a time so that only one output vector is in cache at a time
+        let frame_len = n_channels * sample_len;
+        let format = self.format;
+        let block = (0..n_channels)
+            .map(|channel| {
+                self.buffer[channel * sample_len..]
+                    .chunks(frame_len)
+                    .map(|frame| format.decode(&frame[..sample_len]))
+                    .collect()
+            })
+            .collect();
+        Ok(Some(block))
+    }
+}
+
+impl<R: Read + Seek> Iterator for AudioReader<R> {
+    type Item = Result<Vec<Vec<f32>>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+}
+
+/// Writes a wav file from blocks of de-interleaved samples; the chunk sizes are filled in by `finish`.
+pub struct AudioWriter<W: Write + Seek> {
+    writer: W,
+    header: Header,
+    format: SampleFormat,
+    start: u64,
+    data_start: u64,
+    data_len: u64,
+    buffer: Vec<u8>,
+    ditherer: Ditherer,
+    dithered: Vec<f32>,
+}
+
+impl<W: Write + Seek> AudioWriter<W> {
+    pub fn new(writer: W, header: Header, bit_depth: u8) -> Result<AudioWriter<W>, Error> {
+        AudioWriter::with_dither(writer, header, bit_depth, Dither::None, 0)
+    }
+
+    /// Quantizes the samples with `dither`, whose noise is generated from `seed`.
+    pub fn with_dither(
+        mut writer: W,
+        header: Header,
+        bit_depth: u8,
+        dither: Dither,
+        seed: u64,
+    ) -> Result<AudioWriter<W>, Error> {
+        let format = SampleFormat::from_header(&header)?;
+        if format.bit_depth() != bit_depth {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid bit depth; could not flatten samples.",
+            ));
+        }
+
+        let start = writer.stream_position()?;
+        let data_start = write_header(&mut writer, &header)?;
+
+        Ok(AudioWriter {
+            writer,
+            header,
+            format,
+            start,
+            data_start,
+            data_len: 0,
+            buffer: Vec::new(),
+            ditherer: Ditherer::new(dither, format, header.channel_count as usize, seed),
+            dithered: Vec::new(),
+        })
+    }
+
+    /// Interleaves and writes a block with one slice of samples per channel.
+    pub fn write_block<S: AsRef<[f32]>>(&mut self, block: &[S]) -> Result<(), Error> {
+        let n_channels = self.header.channel_count as usize;
+        if block.len() != n_channels {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Block should have one sample vector per channel.",
+            ));
+        }
+        let n_frames = block[0].as_ref().len();
+        if block.iter().any(|channel| channel.as_ref().len() != n_frames) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "All audio channels should have the same number of samples.",
+            ));
+        }
+
+        let sample_len = self.format.bytes();
+        let frame_len = n_channels * sample_len;
+        let format = self.format;
+        self.buffer.resize(n_frames * frame_len, 0);
+        for (channel, channel_samples) in block.iter().enumerate() {
+            self.dithered.clear();
+            self.dithered.extend_from_slice(channel_samples.as_ref());
+            self.ditherer.process(channel, &mut self.dithered);
+            self.buffer[channel * sample_len..]
+                .chunks_mut(frame_len)
+                .zip(&self.dithered)
+                .for_each(|(frame, &s)| format.encode(s, &mut frame[..sample_len]));
+        }
+
+        self.writer.write_all(&self.buffer)?;
+        self.data_len += self.buffer.len() as u64;
+        Ok(())
+    }
+
+    /// Pads the data chunk, fills in the RIFF and data chunk sizes, and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        finish_data(&mut self.writer, self.start, self.data_start, self.data_len)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::audio::codec::{Header, WAV_FORMAT_IEEE_FLOAT};
+
+    use super::{AudioReader, AudioWriter};
+
+    #[test]
+    fn test_stream_round_trip() {
+        let header = Header::new(WAV_FORMAT_IEEE_FLOAT, 2, 44100, 32);
+        let left: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin()).collect();
+        let right: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.02).cos()).collect();
+
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), header, 32).unwrap();
+        for (l, r) in left.chunks(300).zip(right.chunks(300)) {
+            writer.write_block(&[l, r]).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap();
+        bytes.set_position(0);
+
+        let reader = AudioReader::new(bytes, 256).unwrap();
+        assert_eq!(reader.remaining_frames(), 1000);
+        let blocks: Vec<Vec<Vec<f32>>> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(blocks.len(), 4);
+        assert!(blocks.iter().all(|b| b.len() == 2));
+        let read_left: Vec<f32> = blocks.iter().flat_map(|b| b[0].clone()).collect();
+        let read_right: Vec<f32> = blocks.iter().flat_map(|b| b[1].clone()).collect();
+        assert_eq!(read_left, left);
+        assert_eq!(read_right, right);
+    }
+}
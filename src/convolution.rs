@@ -1,5 +1,23 @@
+use std::ops::Range;
+
 use num::Zero;
-use rustfft::{FftPlanner, num_complex::Complex};
+use rustfft::{FftNum, FftPlanner, num_complex::Complex};
+
+use crate::processor::Processor;
+
+mod convolver;
+mod correlation;
+mod matrix;
+mod non_uniform;
+mod partitioned;
+mod real_fft;
+
+pub use convolver::Convolver;
+pub use correlation::{autocorrelate, cross_correlate, gcc_phat};
+pub use matrix::MatrixConvolver;
+pub use non_uniform::NonUniformConvolver;
+pub use partitioned::PartitionedConvolver;
+pub use real_fft::RealFft;
 
 pub fn fft_convolve(signal: &[Complex<f32>], kernel: &[Complex<f32>],
                     planner: &mut FftPlanner<f32>) -> Vec<Complex<f32>> {
@@ -30,18 +48,96 @@ pub fn fft_convolve(signal: &[Complex<f32>], kernel: &[Complex<f32>],
     normed_product.into_iter().take(len).collect()
 }
 
-pub fn rfft_convolve(signal: &[f32], kernel: &[f32],
-                     planner: &mut FftPlanner<f32>) -> Vec<f32> {
-    fft_convolve(
-        &signal.iter().map(|&x| Complex::new(x, 0.0)).collect::<Vec<Complex<f32>>>(),
-        &kernel.iter().map(|&y| Complex::new(y, 0.0)).collect::<Vec<Complex<f32>>>(),
-        planner,
-    ).iter().map(|p| p.re).collect()
+pub fn rfft_convolve<T: FftNum>(signal: &[T], kernel: &[T],
+                                planner: &mut FftPlanner<T>) -> Vec<T> {
+    // the lengths here are very important; don't change them unless you know what you're doing
+    let len = signal.len() + kernel.len() - 1;
+    let buf_len = len.next_power_of_two().max(2);
+    let mut fft = RealFft::new(buf_len, planner);
+
+    let mut f_signal = vec![Complex::zero(); fft.spectrum_len()];
+    fft.forward(signal, &mut f_signal);
+    let mut f_kernel = vec![Complex::zero(); fft.spectrum_len()];
+    fft.forward(kernel, &mut f_kernel);
+
+    // we have to manually normalize before doing the inverse fft
+    let norm = T::one() / T::from_usize(buf_len).unwrap();
+    f_signal.iter_mut().zip(f_kernel).for_each(|(x, y)| *x = *x * y * norm);
+    let mut convolved = vec![T::zero(); buf_len];
+    fft.inverse(&f_signal, &mut convolved);
+    convolved.truncate(len);
+    convolved
+}
+
+/// Kernels up to this long are convolved directly in the time domain by `convolve`, where that beats the
+/// overhead of the FFTs.
+pub const DIRECT_CONVOLUTION_MAX_LEN: usize = 64;
+
+/// Which part of the full convolution to keep, as in NumPy's `convolve`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Every sample where the signal and kernel overlap: `signal + kernel - 1` samples.
+    Full,
+    /// The middle of the full convolution, as long as the longer of the signal and kernel.
+    Same,
+    /// Only the samples where the signal and kernel overlap completely: `longer - shorter + 1` samples.
+    Valid,
+}
+
+impl Mode {
+    /// The part of the full convolution of inputs `a` and `b` samples long that the mode keeps.
+    pub fn range(self, a: usize, b: usize) -> Range<usize> {
+        let (long, short) = (a.max(b), a.min(b));
+        match self {
+            Mode::Full => 0..long + short - 1,
+            Mode::Same => {
+                let start = (short - 1) / 2;
+                start..start + long
+            }
+            Mode::Valid => short - 1..long,
+        }
+    }
+}
+
+fn direct_convolve_range<T: FftNum>(signal: &[T], kernel: &[T], range: Range<usize>) -> Vec<T> {
+    let (long, short) = if signal.len() >= kernel.len() { (signal, kernel) } else { (kernel, signal) };
+    range
+        .map(|n| {
+            let start = (n + 1).saturating_sub(long.len());
+            let end = short.len().min(n + 1);
+            (start..end).fold(T::zero(), |sum, k| sum + short[k] * long[n - k])
+        })
+        .collect()
+}
+
+/// Full convolution in the time domain, which is exact up to rounding but takes `signal * kernel` operations.
+pub fn direct_convolve<T: FftNum>(signal: &[T], kernel: &[T]) -> Vec<T> {
+    if signal.is_empty() || kernel.is_empty() {
+        return Vec::new();
+    }
+    direct_convolve_range(signal, kernel, Mode::Full.range(signal.len(), kernel.len()))
+}
+
+/// Convolves `signal` with `kernel`, keeping the part of the result given by `mode`. Short kernels are
+/// convolved directly and long ones with FFTs; `f64` samples are worth the cost for long kernels, where rounding
+/// errors in `f32` add up to an audible noise floor.
+pub fn convolve<T: FftNum>(signal: &[T], kernel: &[T], mode: Mode, planner: &mut FftPlanner<T>) -> Vec<T> {
+    if signal.is_empty() || kernel.is_empty() {
+        return Vec::new();
+    }
+    let range = mode.range(signal.len(), kernel.len());
+    if signal.len().min(kernel.len()) <= DIRECT_CONVOLUTION_MAX_LEN {
+        return direct_convolve_range(signal, kernel, range);
+    }
+    let mut convolved = rfft_convolve(signal, kernel, planner);
+    convolved.truncate(range.end);
+    convolved.drain(..range.start);
+    convolved
 }
 
 #[test]
 fn test_rfft_convolve() {
-    let convolved = rfft_convolve(
+    let convolved: Vec<f32> = rfft_convolve(
         &vec![
             1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
             1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
@@ -72,52 +168,63 @@ fn test_rfft_convolve() {
     assert!(convolved.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-4));
 }
 
-fn rfft_convolve_real_time(signal: &[f32], kernel: &[f32], sample_size: usize,
-                           planner: &mut FftPlanner<f32>) -> Vec<f32> {
-    let mut input_buffers = Vec::new();
-    let mut output_buffers = Vec::new();
-    for i in (0..signal.len()).step_by(sample_size) {
-        let input_buffer: Vec<f32> = signal.iter().skip(i).take(sample_size).cloned().collect();
-        let buf_len = input_buffer.len();
-        input_buffers.push(input_buffer);
-
-        let output_buffer: Vec<f32> = vec![0.0; buf_len];
-        output_buffers.push(output_buffer);
-    }
+#[test]
+fn test_convolve_modes() {
+    let mut planner = FftPlanner::new();
+    let signal = [1., 2., 3.];
+    let kernel = [0., 1., 0.5];
+    assert_eq!(convolve(&signal, &kernel, Mode::Full, &mut planner), vec![0., 1., 2.5, 4., 1.5]);
+    assert_eq!(convolve(&signal, &kernel, Mode::Same, &mut planner), vec![1., 2.5, 4.]);
+    assert_eq!(convolve(&signal, &kernel, Mode::Valid, &mut planner), vec![2.5]);
+    assert_eq!(convolve(&signal, &[1., 1.], Mode::Same, &mut planner), vec![1., 3., 5.]);
+    // the kernel may be the longer of the two, like in numpy
+    assert_eq!(convolve(&kernel[..2], &signal, Mode::Valid, &mut planner), vec![1., 2.]);
 
-    let mut signal_buffer = vec![0.0; kernel.len()];
-    for (input_buffer, output_buffer) in input_buffers.iter().zip(output_buffers.iter_mut()) {
-        let start_index = signal_buffer.len().saturating_sub(kernel.len());
-        signal_buffer = signal_buffer.iter()
-            .skip(start_index)
-            .cloned()
-            .chain(input_buffer.iter().map(|&f| f))
-            .collect();
-        let response = rfft_convolve(&signal_buffer, kernel, planner);
-        for (&r, o) in response.iter().skip(kernel.len()).zip(output_buffer.iter_mut()) {
-            *o = r;
-        }
+    // long enough for the fft path, which has to agree with the direct one
+    let signal: Vec<f32> = (0..1000).map(|i| ((i * 7) % 31) as f32 / 15. - 1.).collect();
+    let kernel: Vec<f32> = (0..200).map(|i| (-(i as f32) / 40.).exp()).collect();
+    for &mode in &[Mode::Full, Mode::Same, Mode::Valid] {
+        let range = mode.range(signal.len(), kernel.len());
+        let expected = &direct_convolve(&signal, &kernel)[range];
+        let convolved = convolve(&signal, &kernel, mode, &mut planner);
+        assert_eq!(convolved.len(), expected.len());
+        assert!(convolved.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-3));
     }
+}
 
-    output_buffers.iter().cloned().flatten().collect()
+#[test]
+fn test_convolve_f64() {
+    let signal: Vec<f64> = (0..8000).map(|i| ((i * 7919) % 1000) as f64 / 500. - 1.).collect();
+    let kernel: Vec<f64> = (0..2000).map(|i| (-(i as f64) / 400.).exp()).collect();
+    let expected = direct_convolve(&signal, &kernel);
+    let convolved = convolve(&signal, &kernel, Mode::Full, &mut FftPlanner::new());
+    let max_error = convolved.iter().zip(&expected).map(|(a, b)| (a - b).abs()).fold(0., f64::max);
+    assert!(max_error < 1e-9, "max error {}", max_error);
 }
 
 #[test]
-fn test_rfft_convolve_real_time() {
-    let convolved = rfft_convolve_real_time(
-        &vec![
-            1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
-            1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
-            1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
-            1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
-            1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7.,
-        ],
-        &vec![
-            1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
-        ],
-        5,
-        &mut FftPlanner::new(),
-    );
+fn test_partitioned_convolver() {
+    use crate::processor::process_offline;
+
+    let signal = vec![
+        1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
+        1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
+        1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
+        1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
+        1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7.,
+    ];
+    let kernel = vec![
+        1., 2., 3., 4., 5., 6., 7., 8., 1., 2., 3., 4., 5., 6., 7., 8.,
+    ];
+    let mut convolver = PartitionedConvolver::new(&kernel, 5, &mut FftPlanner::new());
+    let latency = convolver.latency();
+    // feed zeros after the signal to flush out the latency and the convolution's tail
+    let mut samples = vec![signal.iter().cloned()
+        .chain(std::iter::repeat(0.))
+        .take(signal.len() + kernel.len() - 1 + latency)
+        .collect::<Vec<f32>>()];
+    process_offline(&mut convolver, &mut samples, 44100, 3);
+    let convolved = &samples[0][latency..];
     let expected = vec![
         1.00, 4.00, 10.00, 20.00, 35.00, 56.00, 84.00, 120.00, 149.00, 172.00, 190.00,
         204.00, 215.00, 224.00, 232.00, 240.00, 296.00, 336.00, 360.00, 368.00, 360.00,
@@ -130,6 +237,94 @@ fn test_rfft_convolve_real_time() {
         308.00, 318.00, 308.00, 277.00, 224.00, 148.00, 112.00, 131.00, 140.00, 138.00,
         124.00, 97.00, 56.00,
     ];
-    assert!(convolved.len() >= expected.len() - 16);
-    assert!(convolved.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-4));
-}
\ No newline at end of file
+    assert_eq!(convolved.len(), expected.len());
+    assert!(convolved.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-3));
+}
+
+/// Convolves a stream with a fixed kernel a block at a time with no added latency, by convolving each block
+/// together with the input history that the kernel still overlaps.
+pub struct BlockConvolver {
+    kernel: Vec<f32>,
+    fft: Option<RealFft>,
+    f_kernel: Vec<Complex<f32>>,
+    histories: Vec<Vec<f32>>,
+    buffer: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl BlockConvolver {
+    pub fn new(kernel: Vec<f32>) -> BlockConvolver {
+        BlockConvolver {
+            kernel,
+            fft: None,
+            f_kernel: Vec::new(),
+            histories: Vec::new(),
+            buffer: Vec::new(),
+            spectrum: Vec::new(),
+        }
+    }
+}
+
+impl Processor for BlockConvolver {
+    fn prepare(&mut self, _sample_rate: u32, max_block: usize) {
+        // each block is convolved along with the last kernel.len() - 1 inputs, so this is the shortest fft
+        // length that keeps the circular convolution from wrapping into the outputs we keep
+        let history_len = self.kernel.len().saturating_sub(1);
+        let buf_len = (history_len + max_block).next_power_of_two().max(2);
+        let mut fft = RealFft::new(buf_len, &mut FftPlanner::new());
+
+        self.f_kernel = fft.kernel_spectrum(&self.kernel);
+
+        self.buffer = vec![0.; buf_len];
+        self.spectrum = vec![Complex::zero(); fft.spectrum_len()];
+        self.fft = Some(fft);
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        let fft = self.fft.as_mut().expect("BlockConvolver::prepare must be called before process.");
+        let history_len = self.kernel.len().saturating_sub(1);
+        if self.histories.len() < channels.len() {
+            self.histories.resize(channels.len(), vec![0.; history_len]);
+        }
+
+        for (channel, history) in channels.iter_mut().zip(self.histories.iter_mut()) {
+            let block_len = channel.len();
+            self.buffer[..history_len].copy_from_slice(history);
+            self.buffer[history_len..history_len + block_len].copy_from_slice(channel);
+
+            // the history for the next block is the tail of this one's input
+            if block_len >= history_len {
+                history.copy_from_slice(&channel[block_len - history_len..]);
+            } else {
+                history.copy_within(block_len.., 0);
+                history[history_len - block_len..].copy_from_slice(channel);
+            }
+
+            fft.forward(&self.buffer[..history_len + block_len], &mut self.spectrum);
+            self.spectrum.iter_mut().zip(&self.f_kernel).for_each(|(b, k)| *b *= k);
+            fft.inverse(&self.spectrum, &mut self.buffer);
+            channel.copy_from_slice(&self.buffer[history_len..history_len + block_len]);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.histories.iter_mut().for_each(|h| h.iter_mut().for_each(|x| *x = 0.));
+    }
+}
+
+#[test]
+fn test_block_convolver() {
+    use crate::processor::process_offline;
+
+    let signal: Vec<f32> = (0..200).map(|i| ((i * 7) % 13) as f32 - 6.).collect();
+    let kernel: Vec<f32> = (0..37).map(|i| 1. / (i + 1) as f32).collect();
+    let expected = rfft_convolve(&signal, &kernel, &mut FftPlanner::new());
+
+    let mut convolver = BlockConvolver::new(kernel);
+    let mut samples = vec![signal.clone(), signal];
+    process_offline(&mut convolver, &mut samples, 44100, 16);
+    for channel in &samples {
+        assert!(channel.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-3));
+    }
+}
@@ -0,0 +1,93 @@
+use num::Zero;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use super::RealFft;
+
+/// Convolves whole signals with a fixed kernel. The FFT plans, the kernel's spectrum and the working buffers are
+/// kept between calls, so repeatedly convolving signals of similar lengths does no planning or allocation; they
+/// are only rebuilt when a signal needs a longer or shorter FFT than the previous one.
+pub struct Convolver {
+    kernel: Vec<f32>,
+    planner: FftPlanner<f32>,
+    fft: Option<RealFft>,
+    f_kernel: Vec<Complex<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    buffer: Vec<f32>,
+}
+
+impl Convolver {
+    pub fn new(kernel: &[f32]) -> Convolver {
+        assert!(!kernel.is_empty(), "Kernel must be nonempty.");
+        Convolver {
+            kernel: kernel.to_vec(),
+            planner: FftPlanner::new(),
+            fft: None,
+            f_kernel: Vec::new(),
+            spectrum: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn kernel(&self) -> &[f32] {
+        &self.kernel
+    }
+
+    /// The length of the full convolution of a signal of `signal_len` samples with the kernel.
+    pub fn output_len(&self, signal_len: usize) -> usize {
+        signal_len + self.kernel.len() - 1
+    }
+
+    fn plan(&mut self, signal_len: usize) {
+        let buf_len = self.output_len(signal_len).next_power_of_two().max(2);
+        if self.buffer.len() != buf_len {
+            let mut fft = RealFft::new(buf_len, &mut self.planner);
+            self.f_kernel = fft.kernel_spectrum(&self.kernel);
+            self.spectrum = vec![Complex::zero(); fft.spectrum_len()];
+            self.buffer = vec![0.; buf_len];
+            self.fft = Some(fft);
+        }
+    }
+
+    /// Writes the full convolution of `signal` with the kernel into `output`, which has to be
+    /// `output_len(signal.len())` samples long.
+    pub fn convolve_into(&mut self, signal: &[f32], output: &mut [f32]) {
+        let len = self.output_len(signal.len());
+        assert_eq!(output.len(), len, "Output length must be signal length + kernel length - 1.");
+        self.plan(signal.len());
+        let fft = self.fft.as_mut().unwrap();
+        fft.forward(signal, &mut self.spectrum);
+        self.spectrum.iter_mut().zip(&self.f_kernel).for_each(|(x, k)| *x *= k);
+        fft.inverse(&self.spectrum, &mut self.buffer);
+        output.copy_from_slice(&self.buffer[..len]);
+    }
+
+    pub fn convolve(&mut self, signal: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.; self.output_len(signal.len())];
+        self.convolve_into(signal, &mut output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::rfft_convolve;
+
+    use super::Convolver;
+
+    #[test]
+    fn test_matches_rfft_convolve() {
+        let kernel: Vec<f32> = (0..300).map(|i| (-(i as f32) / 50.).exp() * ((i % 5) as f32 - 2.)).collect();
+        let mut convolver = Convolver::new(&kernel);
+        let mut planner = FftPlanner::new();
+        // the second and third signals reuse the plan of the first, and the last needs a new one
+        for &len in &[1000, 900, 1000, 5000] {
+            let signal: Vec<f32> = (0..len).map(|i| ((i * 17) % 23) as f32 / 11. - 1.).collect();
+            let expected = rfft_convolve(&signal, &kernel, &mut planner);
+            let convolved = convolver.convolve(&signal);
+            assert_eq!(convolved.len(), expected.len());
+            assert!(convolved.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-3));
+        }
+    }
+}
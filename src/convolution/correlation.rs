@@ -0,0 +1,103 @@
+use num::Zero;
+use rustfft::{num_complex::Complex, FftNum, FftPlanner};
+
+use super::{convolve, Mode, RealFft};
+
+/// Cross-correlation of `a` with `b`, like NumPy's `correlate`: the result at lag `k` is the sum of
+/// `a[n + k] * b[n]`. In `Mode::Full`, index `i` of the result is lag `i - (b.len() - 1)`.
+pub fn cross_correlate<T: FftNum>(a: &[T], b: &[T], mode: Mode, planner: &mut FftPlanner<T>) -> Vec<T> {
+    let reversed: Vec<T> = b.iter().rev().cloned().collect();
+    convolve(a, &reversed, mode, planner)
+}
+
+/// Autocorrelation of `x` at lags `0..x.len()`; the negative lags mirror these.
+pub fn autocorrelate<T: FftNum>(x: &[T], planner: &mut FftPlanner<T>) -> Vec<T> {
+    if x.is_empty() {
+        return Vec::new();
+    }
+    let mut correlation = cross_correlate(x, x, Mode::Full, planner);
+    correlation.drain(..x.len() - 1);
+    correlation
+}
+
+/// Estimates how many samples `signal` lags behind `reference` with the generalized cross-correlation with phase
+/// transform (GCC-PHAT). Whitening the cross spectrum leaves only the phase, which gives a sharp peak at the delay
+/// even for narrow-band or reverberant signals. Delays are searched up to `max_delay` samples either way, and
+/// the peak is interpolated to a fraction of a sample; a negative delay means that `signal` leads.
+pub fn gcc_phat(signal: &[f32], reference: &[f32], max_delay: usize, planner: &mut FftPlanner<f32>) -> f32 {
+    // long enough that the correlation doesn't wrap around at any lag
+    let buf_len = (signal.len() + reference.len()).next_power_of_two().max(2);
+    let mut fft = RealFft::new(buf_len, planner);
+    let mut f_signal = vec![Complex::zero(); fft.spectrum_len()];
+    fft.forward(signal, &mut f_signal);
+    let mut f_reference = vec![Complex::zero(); fft.spectrum_len()];
+    fft.forward(reference, &mut f_reference);
+
+    for (s, r) in f_signal.iter_mut().zip(&f_reference) {
+        let cross = *s * r.conj();
+        let magnitude = cross.norm();
+        // silent bins, and those made non-finite by NaN or infinite input, carry no phase
+        *s = if magnitude > f32::EPSILON && magnitude.is_finite() { cross / magnitude } else { Complex::zero() };
+    }
+    let mut correlation = vec![0.; buf_len];
+    fft.inverse(&f_signal, &mut correlation);
+
+    // negative lags wrap around to the end of the circular correlation
+    let max_delay = max_delay.min(buf_len / 2 - 1) as isize;
+    let at = |lag: isize| correlation[lag.rem_euclid(buf_len as isize) as usize];
+    // ties, as when there's no signal, go to the smallest delay
+    let peak = (-max_delay..=max_delay)
+        .max_by(|&a, &b| at(a).total_cmp(&at(b)).then(b.abs().cmp(&a.abs())))
+        .unwrap();
+
+    // fit a parabola through the peak and its neighbours
+    let (before, center, after) = (at(peak - 1), at(peak), at(peak + 1));
+    let curvature = before - 2. * center + after;
+    let offset = if curvature < 0. { 0.5 * (before - after) / curvature } else { 0. };
+    peak as f32 + offset
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::Mode;
+
+    use super::{autocorrelate, cross_correlate, gcc_phat};
+
+    #[test]
+    fn test_cross_correlate() {
+        let mut planner = FftPlanner::new();
+        // matches numpy.correlate
+        let a = [1., 2., 3.];
+        let b = [0., 1., 0.5];
+        assert_eq!(cross_correlate(&a, &b, Mode::Full, &mut planner), vec![0.5, 2., 3.5, 3., 0.]);
+        assert_eq!(cross_correlate(&a, &b, Mode::Same, &mut planner), vec![2., 3.5, 3.]);
+        assert_eq!(autocorrelate(&a, &mut planner), vec![14., 8., 3.]);
+    }
+
+    #[test]
+    fn test_gcc_phat() {
+        let mut planner = FftPlanner::new();
+        let reference: Vec<f32> = (0..4000).map(|i| (((i * 7919) % 1000) as f32 / 500. - 1.) * (i as f32 / 300.).sin()).collect();
+        for &delay in &[0, 37, 250] {
+            let signal: Vec<f32> =
+                std::iter::repeat(0.).take(delay).chain(reference.iter().map(|x| 0.5 * x)).collect();
+            assert!((gcc_phat(&signal, &reference, 500, &mut planner) - delay as f32).abs() < 0.5);
+            assert!((gcc_phat(&reference, &signal, 500, &mut planner) + delay as f32).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn test_gcc_phat_without_signal() {
+        let mut planner = FftPlanner::new();
+        let reference: Vec<f32> = (0..1000).map(|i| (i as f32 / 30.).sin()).collect();
+        assert_eq!(gcc_phat(&[0.; 1000], &reference, 100, &mut planner), 0.);
+        assert_eq!(gcc_phat(&[0.; 1000], &[0.; 1000], 100, &mut planner), 0.);
+        for &bad in &[f32::NAN, f32::INFINITY] {
+            let mut signal = reference.clone();
+            signal[500] = bad;
+            assert_eq!(gcc_phat(&signal, &reference, 100, &mut planner), 0.);
+        }
+    }
+}
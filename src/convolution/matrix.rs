@@ -0,0 +1,167 @@
+use num::Zero;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use super::RealFft;
+
+/// Convolves N input channels into M output channels, so that output `o` is the sum of each input `i` convolved
+/// with `kernels[o][i]`. Each input is transformed once no matter how many outputs it feeds, and each output
+/// is transformed back once no matter how many inputs feed it. An empty kernel means that the input does not
+/// feed that output at all.
+///
+/// Like `Convolver`, the plans, buffers and kernel spectra are kept between calls.
+pub struct MatrixConvolver {
+    kernels: Vec<Vec<Vec<f32>>>,
+    n_inputs: usize,
+    kernel_len: usize,
+    planner: FftPlanner<f32>,
+    fft: Option<RealFft>,
+    f_kernels: Vec<Vec<Vec<Complex<f32>>>>,
+    f_inputs: Vec<Vec<Complex<f32>>>,
+    accumulator: Vec<Complex<f32>>,
+    buffer: Vec<f32>,
+}
+
+impl MatrixConvolver {
+    /// `kernels` has one row per output channel and one column per input channel.
+    pub fn new(kernels: Vec<Vec<Vec<f32>>>) -> MatrixConvolver {
+        assert!(!kernels.is_empty(), "The kernel matrix should have at least one output channel.");
+        let n_inputs = kernels[0].len();
+        assert!(n_inputs > 0, "The kernel matrix should have at least one input channel.");
+        assert!(
+            kernels.iter().all(|row| row.len() == n_inputs),
+            "The kernel matrix should have the same number of input channels in every row."
+        );
+        let kernel_len = kernels.iter().flatten().map(|k| k.len()).max().unwrap();
+        assert!(kernel_len > 0, "The kernel matrix should have at least one nonempty kernel.");
+
+        MatrixConvolver {
+            kernels,
+            n_inputs,
+            kernel_len,
+            planner: FftPlanner::new(),
+            fft: None,
+            f_kernels: Vec::new(),
+            f_inputs: Vec::new(),
+            accumulator: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// True-stereo convolution from the four impulse responses measured from each input to each output;
+    /// `lr` is the response at the right output to the left input.
+    pub fn true_stereo(ll: Vec<f32>, lr: Vec<f32>, rl: Vec<f32>, rr: Vec<f32>) -> MatrixConvolver {
+        MatrixConvolver::new(vec![vec![ll, rl], vec![lr, rr]])
+    }
+
+    pub fn n_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.kernels.len()
+    }
+
+    /// The length of each output channel for input channels of `signal_len` samples; shorter kernels' outputs are
+    /// padded with zeros to the longest kernel's.
+    pub fn output_len(&self, signal_len: usize) -> usize {
+        signal_len + self.kernel_len - 1
+    }
+
+    fn plan(&mut self, signal_len: usize) {
+        let buf_len = self.output_len(signal_len).next_power_of_two().max(2);
+        if self.buffer.len() != buf_len {
+            let mut fft = RealFft::new(buf_len, &mut self.planner);
+            let spectrum_len = fft.spectrum_len();
+            self.f_kernels = self
+                .kernels
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|kernel| if kernel.is_empty() { Vec::new() } else { fft.kernel_spectrum(kernel) })
+                        .collect()
+                })
+                .collect();
+            self.f_inputs = vec![vec![Complex::zero(); spectrum_len]; self.n_inputs];
+            self.accumulator = vec![Complex::zero(); spectrum_len];
+            self.buffer = vec![0.; buf_len];
+            self.fft = Some(fft);
+        }
+    }
+
+    /// Convolves `inputs`, which have to be one channel per input and all the same length, returning one channel
+    /// per output of `output_len` samples.
+    pub fn convolve(&mut self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        assert_eq!(inputs.len(), self.n_inputs, "There should be one input channel per kernel matrix column.");
+        let signal_len = inputs[0].len();
+        assert!(
+            inputs.iter().all(|input| input.len() == signal_len),
+            "Input channels should all be the same length."
+        );
+        let len = self.output_len(signal_len);
+        self.plan(signal_len);
+
+        let fft = self.fft.as_mut().unwrap();
+        for (input, f_input) in inputs.iter().zip(self.f_inputs.iter_mut()) {
+            fft.forward(input, f_input);
+        }
+
+        let mut outputs = Vec::with_capacity(self.kernels.len());
+        for f_row in &self.f_kernels {
+            self.accumulator.iter_mut().for_each(|a| *a = Complex::zero());
+            for (f_kernel, f_input) in f_row.iter().zip(&self.f_inputs) {
+                for ((a, &k), &x) in self.accumulator.iter_mut().zip(f_kernel).zip(f_input) {
+                    *a += k * x;
+                }
+            }
+            fft.inverse(&self.accumulator, &mut self.buffer);
+            outputs.push(self.buffer[..len].to_vec());
+        }
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::rfft_convolve;
+
+    use super::MatrixConvolver;
+
+    #[test]
+    fn test_true_stereo() {
+        let left: Vec<f32> = (0..500).map(|i| ((i * 7) % 19) as f32 / 9. - 1.).collect();
+        let right: Vec<f32> = (0..500).map(|i| ((i * 11) % 23) as f32 / 11. - 1.).collect();
+        let ll: Vec<f32> = (0..100).map(|i| (-(i as f32) / 20.).exp()).collect();
+        let lr: Vec<f32> = (0..60).map(|i| 0.5 * (-(i as f32) / 10.).exp()).collect();
+        let rl = vec![0., 0., 0.25];
+        let rr: Vec<f32> = (0..80).map(|i| if i % 3 == 0 { 1. } else { -0.5 }).collect();
+
+        let inputs = vec![left, right];
+        let mut planner = FftPlanner::new();
+        let mut expected = vec![vec![0.; 599]; 2];
+        for &(o, i, kernel) in &[(0, 0, &ll), (0, 1, &rl), (1, 0, &lr), (1, 1, &rr)] {
+            for (e, c) in expected[o].iter_mut().zip(rfft_convolve(&inputs[i], kernel, &mut planner)) {
+                *e += c;
+            }
+        }
+
+        let mut convolver = MatrixConvolver::true_stereo(ll, lr, rl, rr);
+        let outputs = convolver.convolve(&inputs);
+        assert_eq!(outputs.len(), 2);
+        for (output, expected) in outputs.iter().zip(&expected) {
+            assert_eq!(output.len(), 599);
+            assert!(output.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-3));
+        }
+    }
+
+    #[test]
+    fn test_empty_kernels_are_skipped() {
+        let mut convolver = MatrixConvolver::new(vec![vec![vec![1., 0.5], vec![]], vec![vec![], vec![2.]]]);
+        let outputs = convolver.convolve(&[vec![1., 2., 3.], vec![4., 5., 6.]]);
+        let expected = vec![vec![1., 2.5, 4., 1.5], vec![8., 10., 12., 0.]];
+        for (output, expected) in outputs.iter().zip(&expected) {
+            assert!(output.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5));
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use rustfft::FftPlanner;
+
+use crate::processor::Processor;
+
+use super::PartitionedConvolver;
+
+/// A part of the kernel's tail, convolved with uniform partitions and delayed to its place in the kernel. Each
+/// channel has its own segments, whose convolvers share the kernel's spectra.
+#[derive(Clone)]
+struct Segment {
+    convolver: PartitionedConvolver,
+    delay: Vec<f32>,
+    delay_position: usize,
+}
+
+impl Segment {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.convolver.process(&mut [&mut *samples]);
+        if self.delay.is_empty() {
+            return;
+        }
+        for s in samples.iter_mut() {
+            std::mem::swap(s, &mut self.delay[self.delay_position]);
+            self.delay_position = (self.delay_position + 1) % self.delay.len();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.convolver.reset();
+        self.delay.iter_mut().for_each(|x| *x = 0.);
+        self.delay_position = 0;
+    }
+}
+
+#[derive(Clone)]
+struct ChannelState {
+    history: Vec<f32>,
+    history_position: usize,
+    segments: Vec<Segment>,
+    input: Vec<f32>,
+    work: Vec<f32>,
+}
+
+/// Zero latency convolver for long kernels. The head of the kernel is applied directly in the time domain, and
+/// the tail is split into segments whose partition sizes double along the kernel. Each segment starts late
+/// enough in the kernel to hide its partitioned convolver's latency, so large, cheap partitions handle most of a
+/// long reverb tail while the output still responds to every input sample immediately.
+pub struct NonUniformConvolver {
+    head: Vec<f32>,
+    template: ChannelState,
+    channels: Vec<ChannelState>,
+    max_block: usize,
+}
+
+impl NonUniformConvolver {
+    /// `head_len` samples of the kernel are convolved directly, and the tail's partitions start at `head_len`
+    /// samples and grow up to `max_partition_size`.
+    pub fn new(
+        kernel: &[f32],
+        head_len: usize,
+        max_partition_size: usize,
+        planner: &mut FftPlanner<f32>,
+    ) -> NonUniformConvolver {
+        assert!(head_len > 0, "Head length must be nonzero.");
+        // an empty kernel is padded to a single zero, so that there's always a head to convolve with
+        let kernel = if kernel.is_empty() { &[0.][..] } else { kernel };
+        let head_len = head_len.min(kernel.len());
+        let max_partition_size = max_partition_size.max(head_len);
+
+        let mut segments = Vec::new();
+        let mut offset = head_len;
+        let mut partition_size = head_len;
+        while offset < kernel.len() {
+            // two partitions of each size keep every segment's offset at least as large as its latency
+            let len = if partition_size == max_partition_size {
+                kernel.len() - offset
+            } else {
+                (2 * partition_size).min(kernel.len() - offset)
+            };
+            let convolver = PartitionedConvolver::new(&kernel[offset..offset + len], partition_size, planner);
+            let delay = offset - convolver.latency();
+            segments.push(Segment {
+                convolver,
+                delay: vec![0.; delay],
+                delay_position: 0,
+            });
+            offset += len;
+            partition_size = (2 * partition_size).min(max_partition_size);
+        }
+
+        NonUniformConvolver {
+            head: kernel[..head_len].to_vec(),
+            template: ChannelState {
+                history: vec![0.; head_len],
+                history_position: 0,
+                segments,
+                input: Vec::new(),
+                work: Vec::new(),
+            },
+            channels: Vec::new(),
+            max_block: 0,
+        }
+    }
+}
+
+impl Processor for NonUniformConvolver {
+    fn prepare(&mut self, _sample_rate: u32, max_block: usize) {
+        self.max_block = max_block;
+        self.template.input = vec![0.; max_block];
+        self.template.work = vec![0.; max_block];
+        for state in self.channels.iter_mut() {
+            state.input.resize(max_block, 0.);
+            state.work.resize(max_block, 0.);
+        }
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        assert!(self.max_block > 0, "NonUniformConvolver::prepare must be called before process.");
+        while self.channels.len() < channels.len() {
+            self.channels.push(self.template.clone());
+        }
+
+        for (channel, state) in channels.iter_mut().zip(self.channels.iter_mut()) {
+            let len = channel.len();
+            state.input[..len].copy_from_slice(channel);
+
+            let history_len = state.history.len();
+            for (i, c) in channel.iter_mut().enumerate() {
+                state.history[state.history_position] = state.input[i];
+                *c = self
+                    .head
+                    .iter()
+                    .enumerate()
+                    .map(|(k, h)| h * state.history[(state.history_position + history_len - k) % history_len])
+                    .sum();
+                state.history_position = (state.history_position + 1) % history_len;
+            }
+
+            for segment in state.segments.iter_mut() {
+                state.work[..len].copy_from_slice(&state.input[..len]);
+                segment.process(&mut state.work[..len]);
+                channel.iter_mut().zip(&state.work[..len]).for_each(|(c, w)| *c += w);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for state in self.channels.iter_mut() {
+            state.history.iter_mut().for_each(|x| *x = 0.);
+            state.history_position = 0;
+            state.segments.iter_mut().for_each(|s| s.reset());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::{convolve, rfft_convolve, Mode};
+    use crate::processor::{process_offline, Processor};
+
+    use super::NonUniformConvolver;
+
+    #[test]
+    fn test_zero_latency_long_kernel() {
+        let signal: Vec<f32> = (0..6000).map(|i| ((i * 13) % 29) as f32 / 14. - 1.).collect();
+        let kernel: Vec<f32> = (0..5000).map(|i| (-(i as f32) / 1000.).exp() * ((i % 7) as f32 - 3.) / 3.).collect();
+        let mut planner = FftPlanner::new();
+        let expected = rfft_convolve(&signal, &kernel, &mut planner);
+
+        let mut convolver = NonUniformConvolver::new(&kernel, 32, 512, &mut planner);
+        assert_eq!(convolver.latency(), 0);
+        let mut samples = vec![signal.clone(); 2];
+        samples.iter_mut().for_each(|s| s.resize(expected.len(), 0.));
+        process_offline(&mut convolver, &mut samples, 48000, 48);
+        for channel in &samples {
+            let max_error = channel
+                .iter()
+                .zip(&expected)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0., f32::max);
+            assert!(max_error < 1e-2, "max error {}", max_error);
+        }
+    }
+
+    #[test]
+    fn test_multi_second_kernel() {
+        // a three second reverb tail at 48 kHz, shared by both channels
+        let kernel: Vec<f32> = (0..144000)
+            .map(|i| (-(i as f32) / 30000.).exp() * (((i * 7919) % 1000) as f32 / 500. - 1.))
+            .collect();
+        let signal: Vec<f32> = (0..8000).map(|i| ((i * 13) % 29) as f32 / 14. - 1.).collect();
+        let mut planner = FftPlanner::new();
+        let expected = convolve(&signal, &kernel, Mode::Full, &mut planner);
+
+        let mut convolver = NonUniformConvolver::new(&kernel, 64, 8192, &mut planner);
+        let mut samples = vec![signal.clone(), signal.iter().map(|x| -0.5 * x).collect()];
+        samples.iter_mut().for_each(|s| s.resize(expected.len(), 0.));
+        process_offline(&mut convolver, &mut samples, 48000, 256);
+        for (channel, gain) in samples.iter().zip(&[1., -0.5]) {
+            let max_error = channel
+                .iter()
+                .zip(&expected)
+                .map(|(a, b)| (a - gain * b).abs())
+                .fold(0., f32::max);
+            assert!(max_error < 1e-3, "max error {}", max_error);
+        }
+    }
+
+    #[test]
+    fn test_empty_kernel() {
+        let mut convolver = NonUniformConvolver::new(&[], 32, 512, &mut FftPlanner::new());
+        let mut samples = vec![vec![1.; 100]; 2];
+        process_offline(&mut convolver, &mut samples, 48000, 48);
+        assert!(samples.iter().flatten().all(|&x| x == 0.));
+    }
+}
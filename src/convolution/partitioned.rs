@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use num::Zero;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::processor::Processor;
+
+use super::RealFft;
+
+#[derive(Clone)]
+struct ChannelState {
+    // the previous and current partitions of input, which overlap-save transforms together
+    window: Vec<f32>,
+    // spectra of the most recent input windows, indexed as a ring buffer
+    delay_line: Vec<Vec<Complex<f32>>>,
+    output: Vec<f32>,
+}
+
+/// Uniformly partitioned overlap-save convolver. The kernel is split into partitions of `partition_size`
+/// samples which are transformed once up front; each partition of input is transformed once and kept in a
+/// frequency-domain delay line, so every partition costs the same, allocation-free amount of work regardless of
+/// the length of the kernel.
+///
+/// As a `Processor`, it accepts blocks of any size and has a latency of `partition_size - 1` samples. Clones
+/// share the kernel's spectra, and only copy the state of the channels they've processed.
+#[derive(Clone)]
+pub struct PartitionedConvolver {
+    partition_size: usize,
+    fft: RealFft,
+    f_partitions: Arc<Vec<Vec<Complex<f32>>>>,
+    channels: Vec<ChannelState>,
+    fill: usize,
+    newest: usize,
+    accumulator: Vec<Complex<f32>>,
+    buffer: Vec<f32>,
+}
+
+impl PartitionedConvolver {
+    pub fn new(kernel: &[f32], partition_size: usize, planner: &mut FftPlanner<f32>) -> PartitionedConvolver {
+        assert!(partition_size > 0, "Partition size must be nonzero.");
+        let buf_len = 2 * partition_size;
+        let mut fft = RealFft::new(buf_len, planner);
+
+        let f_partitions: Vec<Vec<Complex<f32>>> =
+            kernel.chunks(partition_size).map(|partition| fft.kernel_spectrum(partition)).collect();
+
+        PartitionedConvolver {
+            partition_size,
+            f_partitions: Arc::new(f_partitions),
+            channels: Vec::new(),
+            fill: 0,
+            newest: 0,
+            accumulator: vec![Complex::zero(); fft.spectrum_len()],
+            buffer: vec![0.; buf_len],
+            fft,
+        }
+    }
+
+    pub fn partition_size(&self) -> usize {
+        self.partition_size
+    }
+
+    fn channel_state(&self) -> ChannelState {
+        let buf_len = 2 * self.partition_size;
+        ChannelState {
+            window: vec![0.; buf_len],
+            delay_line: vec![vec![Complex::zero(); self.fft.spectrum_len()]; self.f_partitions.len().max(1)],
+            output: vec![0.; self.partition_size],
+        }
+    }
+
+    /// Convolves one full partition of a channel's input, which has to have been written into the second half of
+    /// its window, and leaves the result in its output buffer.
+    fn convolve_partition(&mut self, channel: usize) {
+        let n = self.partition_size;
+        let state = &mut self.channels[channel];
+        let n_partitions = state.delay_line.len();
+        self.fft.forward(&state.window, &mut state.delay_line[self.newest]);
+
+        self.accumulator.iter_mut().for_each(|a| *a = Complex::zero());
+        for (age, f_partition) in self.f_partitions.iter().enumerate() {
+            let spectrum = &state.delay_line[(self.newest + n_partitions - age) % n_partitions];
+            for ((a, &x), &h) in self.accumulator.iter_mut().zip(spectrum).zip(f_partition) {
+                *a += x * h;
+            }
+        }
+        self.fft.inverse(&self.accumulator, &mut self.buffer);
+
+        // overlap-save: the first half of the circular convolution is aliased, so only the second half is kept
+        state.output.copy_from_slice(&self.buffer[n..]);
+        state.window.copy_within(n.., 0);
+    }
+}
+
+impl Processor for PartitionedConvolver {
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        while self.channels.len() < channels.len() {
+            let state = self.channel_state();
+            self.channels.push(state);
+        }
+
+        let n = self.partition_size;
+        let block_len = channels.first().map_or(0, |c| c.len());
+        let mut start = 0;
+        while start < block_len {
+            // copy up to the end of the current partition, so that each partition is convolved as soon as it fills
+            let len = (n - self.fill).min(block_len - start);
+            for (channel, state) in channels.iter().zip(self.channels.iter_mut()) {
+                state.window[n + self.fill..n + self.fill + len].copy_from_slice(&channel[start..start + len]);
+            }
+            // each sample is output n - 1 samples after it came in, so every sample but the last of a partition
+            // comes from the previous partition's output
+            let completes_partition = self.fill + len == n;
+            let from_previous = if completes_partition { len - 1 } else { len };
+            for (channel, state) in channels.iter_mut().zip(self.channels.iter()) {
+                for (i, c) in channel[start..start + from_previous].iter_mut().enumerate() {
+                    *c = state.output[self.fill + i + 1];
+                }
+            }
+            if completes_partition {
+                for channel in 0..channels.len() {
+                    self.convolve_partition(channel);
+                }
+                self.newest = (self.newest + 1) % self.channels[0].delay_line.len();
+                for (channel, state) in channels.iter_mut().zip(self.channels.iter()) {
+                    channel[start + len - 1] = state.output[0];
+                }
+            }
+            self.fill = (self.fill + len) % n;
+            start += len;
+        }
+    }
+
+    fn reset(&mut self) {
+        for state in self.channels.iter_mut() {
+            state.window.iter_mut().for_each(|x| *x = 0.);
+            state.output.iter_mut().for_each(|x| *x = 0.);
+            state
+                .delay_line
+                .iter_mut()
+                .for_each(|s| s.iter_mut().for_each(|x| *x = Complex::zero()));
+        }
+        self.fill = 0;
+        self.newest = 0;
+    }
+
+    fn latency(&self) -> usize {
+        self.partition_size - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::rfft_convolve;
+    use crate::processor::{process_offline, Processor};
+
+    use super::PartitionedConvolver;
+
+    #[test]
+    fn test_matches_rfft_convolve() {
+        let signal: Vec<f32> = (0..3000).map(|i| ((i * 31) % 17) as f32 / 8. - 1.).collect();
+        let kernel: Vec<f32> = (0..1000).map(|i| (-(i as f32) / 200.).exp() * if i % 2 == 0 { 1. } else { -0.5 }).collect();
+        let mut planner = FftPlanner::new();
+        let expected = rfft_convolve(&signal, &kernel, &mut planner);
+
+        let mut convolver = PartitionedConvolver::new(&kernel, 128, &mut planner);
+        let latency = convolver.latency();
+        let mut samples = vec![signal.clone(); 2];
+        samples.iter_mut().for_each(|s| s.resize(expected.len() + latency, 0.));
+        // a block size which doesn't divide the partition size exercises the buffering
+        process_offline(&mut convolver, &mut samples, 44100, 100);
+        for channel in &samples {
+            let max_error = channel[latency..]
+                .iter()
+                .zip(&expected)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0., f32::max);
+            assert!(max_error < 1e-3, "max error {}", max_error);
+        }
+    }
+}
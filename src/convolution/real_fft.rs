@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use num::Zero;
+use rustfft::{num_complex::Complex, Fft, FftNum, FftPlanner};
+
+/// FFT of real signals of even length `len`, computed with a complex FFT of half the length by packing even and
+/// odd samples into the real and imaginary parts. Only the `len / 2 + 1` non-redundant bins are produced.
+#[derive(Clone)]
+pub struct RealFft<T: FftNum = f32> {
+    len: usize,
+    fft: Arc<dyn Fft<T>>,
+    ifft: Arc<dyn Fft<T>>,
+    twiddles: Vec<Complex<T>>,
+    buffer: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: FftNum> RealFft<T> {
+    pub fn new(len: usize, planner: &mut FftPlanner<T>) -> RealFft<T> {
+        assert!(len >= 2 && len % 2 == 0, "Real FFT length must be even and nonzero.");
+        let half = len / 2;
+        let fft = planner.plan_fft_forward(half);
+        let ifft = planner.plan_fft_inverse(half);
+        // a recurrence in double precision avoids evaluating a sine and cosine per bin, and the second quarter of
+        // the circle mirrors the first
+        let step = Complex::from_polar(1., -2. * std::f64::consts::PI / len as f64);
+        let mut twiddles: Vec<Complex<T>> = std::iter::successors(Some(Complex::new(1f64, 0.)), |w| Some(w * step))
+            .take(half / 2 + 1)
+            .map(|w| Complex::new(T::from_f64(w.re).unwrap(), T::from_f64(w.im).unwrap()))
+            .collect();
+        for k in half / 2 + 1..=half {
+            let mirror = -twiddles[half - k].conj();
+            twiddles.push(mirror);
+        }
+        let scratch_len = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+        RealFft {
+            len,
+            fft,
+            ifft,
+            twiddles,
+            buffer: vec![Complex::zero(); half],
+            scratch: vec![Complex::zero(); scratch_len],
+        }
+    }
+
+    pub fn spectrum_len(&self) -> usize {
+        self.len / 2 + 1
+    }
+
+    /// Transforms `input`, which is zero padded up to `len`, into the first `len / 2 + 1` bins of its spectrum.
+    pub fn forward(&mut self, input: &[T], spectrum: &mut [Complex<T>]) {
+        let half = self.len / 2;
+        let input = &input[..input.len().min(self.len)];
+        let n_pairs = input.len().div_ceil(2);
+        for (b, pair) in self.buffer.iter_mut().zip(input.chunks(2)) {
+            *b = Complex::new(pair[0], pair.get(1).cloned().unwrap_or_else(T::zero));
+        }
+        self.buffer[n_pairs..].iter_mut().for_each(|b| *b = Complex::zero());
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // separate the spectra of the even and odd samples, then combine them with one radix-2 butterfly
+        let z = self.buffer[0];
+        spectrum[0] = Complex::new(z.re + z.im, T::zero());
+        spectrum[half] = Complex::new(z.re - z.im, T::zero());
+        let one_half = T::from_f64(0.5).unwrap();
+        let mirrored = self.buffer[1..].iter().zip(self.buffer[1..].iter().rev());
+        for ((s, &twiddle), (&z, &z_mirror)) in spectrum[1..half].iter_mut().zip(&self.twiddles[1..]).zip(mirrored) {
+            let z_mirror = z_mirror.conj();
+            let even = z + z_mirror;
+            let odd = z - z_mirror;
+            *s = (even + twiddle * Complex::new(odd.im, -odd.re)) * one_half;
+        }
+    }
+
+    /// The spectrum of `kernel`, scaled so that transforming its product with another spectrum back with `inverse`
+    /// gives the convolution itself rather than `len` times it.
+    pub fn kernel_spectrum(&mut self, kernel: &[T]) -> Vec<Complex<T>> {
+        // fold the inverse fft's normalization into the kernel so that it costs nothing per transform
+        let norm = T::one() / T::from_usize(self.len).unwrap();
+        let kernel: Vec<T> = kernel.iter().map(|&k| k * norm).collect();
+        let mut spectrum = vec![Complex::zero(); self.spectrum_len()];
+        self.forward(&kernel, &mut spectrum);
+        spectrum
+    }
+
+    /// Inverse of `forward`; like rustfft's inverse transforms, the output is not normalized, so it is `len`
+    /// times the original signal.
+    pub fn inverse(&mut self, spectrum: &[Complex<T>], output: &mut [T]) {
+        let half = self.len / 2;
+        let mirrored = spectrum[..half].iter().zip(spectrum[1..=half].iter().rev());
+        for ((b, &twiddle), (&x, &x_mirror)) in self.buffer.iter_mut().zip(&self.twiddles).zip(mirrored) {
+            let x_mirror = x_mirror.conj();
+            let even = x + x_mirror;
+            let odd = (x - x_mirror) * twiddle.conj();
+            *b = even + Complex::new(-odd.im, odd.re);
+        }
+        self.ifft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+        for (pair, b) in output.chunks_mut(2).zip(&self.buffer) {
+            pair[0] = b.re;
+            if pair.len() > 1 {
+                pair[1] = b.im;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    use super::RealFft;
+
+    #[test]
+    fn test_matches_complex_fft() {
+        let mut planner = FftPlanner::new();
+        let signal: Vec<f32> = (0..64).map(|i| ((i * 5) % 11) as f32 - 5.).collect();
+        let mut expected: Vec<Complex<f32>> = signal.iter().map(|&x| Complex::new(x, 0.)).collect();
+        planner.plan_fft_forward(64).process(&mut expected);
+
+        let mut fft = RealFft::new(64, &mut planner);
+        let mut spectrum = vec![Complex::new(0., 0.); fft.spectrum_len()];
+        fft.forward(&signal, &mut spectrum);
+        assert!(spectrum.iter().zip(&expected).all(|(a, b)| (a - b).norm() < 1e-3));
+
+        let mut output = vec![0.; 64];
+        fft.inverse(&spectrum, &mut output);
+        assert!(output.iter().zip(&signal).all(|(a, b)| (a / 64. - b).abs() < 1e-4));
+    }
+}
@@ -1,47 +1,317 @@
-fn median(samples: &[f32]) -> f32 {
-    let mut samples: Vec<f32> = samples.iter().cloned().collect();
-    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    samples[samples.len() / 2]
+use crate::processor::Processor;
+
+mod biquad;
+mod rank;
+mod statistics;
+
+pub use biquad::{Biquad, BiquadCascade, BiquadCoefficients, BiquadShape};
+pub use rank::RunningRank;
+pub use statistics::RunningMoments;
+
+/// How a filter sees past the ends of a signal. All modes but `Shrink` center the window on each sample and
+/// give an output as long as the input.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Boundary {
+    /// Mirrors the signal about its edges, repeating the edge samples: `d c b a | a b c d | d c b a`.
+    Reflect,
+    /// Pads the signal with a constant value.
+    Constant(f32),
+    /// Repeats the edge samples.
+    Nearest,
+    /// Treats the signal as periodic.
+    Wrap,
+    /// Only filters where the window fits inside the signal, giving `len - filter_length + 1` samples, or none
+    /// if the signal is shorter than the window.
+    Shrink,
+}
+
+// the signal padded according to `boundary`, so that sliding a window of `filter_length` over it gives the
+// output for that boundary
+fn pad(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    assert!(filter_length > 0, "Filter length must be nonzero.");
+    let n = samples.len() as isize;
+    if n == 0 || boundary == Boundary::Shrink {
+        return samples.to_vec();
+    }
+    let before = (filter_length / 2) as isize;
+    let after = (filter_length - 1) as isize - before;
+    (-before..n + after)
+        .map(|i| match boundary {
+            _ if 0 <= i && i < n => samples[i as usize],
+            Boundary::Reflect => {
+                let i = i.rem_euclid(2 * n);
+                samples[if i < n { i } else { 2 * n - 1 - i } as usize]
+            }
+            Boundary::Constant(value) => value,
+            Boundary::Nearest => samples[i.clamp(0, n - 1) as usize],
+            Boundary::Wrap => samples[i.rem_euclid(n) as usize],
+            Boundary::Shrink => unreachable!(),
+        })
+        .collect()
 }
 
-pub fn median_filter(samples: &[f32], filter_length: usize) -> Vec<f32> {
-    (0..samples.len() - filter_length + 1)
-        .map(|i| median(&samples[i..i + filter_length]))
+// a streaming filter's window primed as if the stream were preceded by silence, one sample short of full so that
+// the first sample pushed fills it
+fn pad_with_silence<W>(mut window: W, filter_length: usize, mut push: impl FnMut(&mut W, f32)) -> W {
+    (1..filter_length).for_each(|_| push(&mut window, 0.));
+    window
+}
+
+fn running_rank_filter(samples: &[f32], mut running: RunningRank, boundary: Boundary) -> Vec<f32> {
+    pad(samples, running.window_len(), boundary)
+        .iter()
+        .filter_map(|&x| running.push(x))
         .collect()
 }
 
-fn mean(samples: &[f32]) -> f32 {
-    samples.iter().sum::<f32>() / samples.len() as f32
+/// Replaces each sample with the sample of `rank` in the window around it, where rank 0 is the smallest. Samples
+/// are ordered by `f32::total_cmp`, so NaNs sort above everything else, or below it when their sign bit is set.
+pub fn rank_filter(samples: &[f32], filter_length: usize, rank: usize, boundary: Boundary) -> Vec<f32> {
+    running_rank_filter(samples, RunningRank::new(filter_length, rank), boundary)
+}
+
+/// Replaces each sample with a percentile, from 0 to 100, of the window around it.
+pub fn percentile_filter(samples: &[f32], filter_length: usize, percentile: f64, boundary: Boundary) -> Vec<f32> {
+    running_rank_filter(samples, RunningRank::percentile(filter_length, percentile), boundary)
+}
+
+/// Replaces each sample with the median of the window around it, or the upper of the middle two samples for
+/// even lengths.
+pub fn median_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    rank_filter(samples, filter_length, filter_length / 2, boundary)
+}
+
+fn moments_filter(
+    samples: &[f32],
+    filter_length: usize,
+    boundary: Boundary,
+    statistic: impl Fn(&RunningMoments) -> f64,
+) -> Vec<f32> {
+    let mut moments = RunningMoments::new(filter_length);
+    pad(samples, filter_length, boundary)
+        .iter()
+        .filter_map(|&x| {
+            moments.push(x);
+            if moments.is_full() {
+                Some(statistic(&moments) as f32)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn mean_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    moments_filter(samples, filter_length, boundary, RunningMoments::mean)
+}
+
+/// The root mean square level of the window around each sample.
+pub fn rms_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    moments_filter(samples, filter_length, boundary, RunningMoments::rms)
+}
+
+/// The population variance of the window around each sample.
+pub fn variance_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    moments_filter(samples, filter_length, boundary, RunningMoments::variance)
+}
+
+pub fn min_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    rank_filter(samples, filter_length, 0, boundary)
+}
+
+pub fn max_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    assert!(filter_length > 0, "Filter length must be nonzero.");
+    rank_filter(samples, filter_length, filter_length - 1, boundary)
+}
+
+/// The largest magnitude in the window around each sample, which is the envelope a peak meter follows.
+pub fn peak_filter(samples: &[f32], filter_length: usize, boundary: Boundary) -> Vec<f32> {
+    assert!(filter_length > 0, "Filter length must be nonzero.");
+    let mut running = RunningRank::new(filter_length, filter_length - 1);
+    pad(samples, filter_length, boundary)
+        .iter()
+        .filter_map(|x| running.push(x.abs()))
+        .collect()
+}
+
+/// Streaming median filter over the last `filter_length` samples of each channel.
+pub struct MedianFilter {
+    filter_length: usize,
+    windows: Vec<RunningRank>,
+}
+
+impl MedianFilter {
+    pub fn new(filter_length: usize) -> MedianFilter {
+        assert!(filter_length > 0, "Filter length must be nonzero.");
+        MedianFilter {
+            filter_length,
+            windows: Vec::new(),
+        }
+    }
+
+    fn silent_window(&self) -> RunningRank {
+        let window = RunningRank::new(self.filter_length, self.filter_length / 2);
+        pad_with_silence(window, self.filter_length, |w, x| {
+            w.push(x);
+        })
+    }
+}
+
+impl Processor for MedianFilter {
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        while self.windows.len() < channels.len() {
+            self.windows.push(self.silent_window());
+        }
+        for (channel, window) in channels.iter_mut().zip(self.windows.iter_mut()) {
+            for s in channel.iter_mut() {
+                *s = window.push(*s).unwrap();
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.windows = (0..self.windows.len()).map(|_| self.silent_window()).collect();
+    }
+
+    fn latency(&self) -> usize {
+        self.filter_length / 2
+    }
 }
 
-pub fn mean_filter(samples: &[f32], filter_length: usize) -> Vec<f32> {
-    let mut total: f32 = samples.iter().take(filter_length).sum();
-    let float_filter_length = filter_length as f32;
-    (filter_length..samples.len()).map(|i| {
-        total += samples[i] - samples[i - filter_length];
-        total / float_filter_length
-    }).collect()
+/// Streaming moving average over the last `filter_length` samples of each channel.
+pub struct MeanFilter {
+    filter_length: usize,
+    windows: Vec<RunningMoments>,
 }
 
+impl MeanFilter {
+    pub fn new(filter_length: usize) -> MeanFilter {
+        assert!(filter_length > 0, "Filter length must be nonzero.");
+        MeanFilter {
+            filter_length,
+            windows: Vec::new(),
+        }
+    }
+
+    fn silent_window(&self) -> RunningMoments {
+        pad_with_silence(RunningMoments::new(self.filter_length), self.filter_length, RunningMoments::push)
+    }
+}
+
+impl Processor for MeanFilter {
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        while self.windows.len() < channels.len() {
+            self.windows.push(self.silent_window());
+        }
+        for (channel, window) in channels.iter_mut().zip(self.windows.iter_mut()) {
+            for s in channel.iter_mut() {
+                window.push(*s);
+                *s = window.mean() as f32;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.windows = (0..self.windows.len()).map(|_| self.silent_window()).collect();
+    }
+
+    fn latency(&self) -> usize {
+        self.filter_length / 2
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::median_filter;
     use super::mean_filter;
+    use super::{max_filter, min_filter, peak_filter, rms_filter, variance_filter};
+    use super::{percentile_filter, rank_filter};
+    use super::{Boundary, MeanFilter, MedianFilter};
+    use crate::processor::process_offline;
 
     #[test]
     fn test_median_filter() {
         let samples = vec![3., 2., 4., 5., 1., 2., 3., 4., 5., 6., 3., 2., 1.];
         let expected = vec![3., 4., 4., 2., 2., 3., 4., 5., 5., 3., 2.];
-        let computed = median_filter(&samples, 3);
+        let computed = median_filter(&samples, 3, Boundary::Shrink);
         assert!(computed == expected);
     }
 
+    #[test]
+    fn test_percentile_filters() {
+        let samples = vec![3., 2., 4., 5., 1., 2., f32::NAN, 4., 5., 6., 3., 2., 1.];
+        let minimum = percentile_filter(&samples, 3, 0., Boundary::Nearest);
+        assert_eq!(minimum, vec![2., 2., 2., 1., 1., 1., 2., 4., 4., 3., 2., 1., 1.]);
+        assert_eq!(minimum, rank_filter(&samples, 3, 0, Boundary::Nearest));
+        // the maximum of a window with a NaN in it is NaN
+        let maximum = percentile_filter(&samples, 3, 100., Boundary::Nearest);
+        assert_eq!(maximum[..5], [3., 4., 5., 5., 5.]);
+        assert!(maximum[5..8].iter().all(|x| x.is_nan()));
+        assert_eq!(maximum[8..], [6., 6., 6., 3., 2.]);
+        assert_eq!(median_filter(&samples, 3, Boundary::Shrink)[4..7], [2., 4., 5.]);
+    }
+
     #[test]
     fn test_mean_filter() {
         let samples = vec![3., 2., 4., 5., 1., 2., 3., 4., 5., 6., 3., 2., 1.];
-        let expected = vec![3.6666667, 3.3333333, 2.6666667, 2.0, 3.0, 4.0, 5.0, 4.6666665, 3.6666667, 2.0];
-        let computed = mean_filter(&samples, 3);
+        let expected = vec![3.0, 3.6666667, 3.3333333, 2.6666667, 2.0, 3.0, 4.0, 5.0, 4.6666665, 3.6666667, 2.0];
+        let computed = mean_filter(&samples, 3, Boundary::Shrink);
         assert!(computed == expected);
     }
+
+    #[test]
+    fn test_statistics_filters() {
+        let samples = vec![1., -3., 2., 0., -1.];
+        let near = |computed: Vec<f32>, expected: &[f32]| {
+            computed.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6)
+        };
+        let rms: Vec<f32> = [5., 6.5, 2., 0.5].iter().map(|x: &f32| x.sqrt()).collect();
+        assert!(near(rms_filter(&samples, 2, Boundary::Shrink), &rms));
+        assert!(near(variance_filter(&samples, 2, Boundary::Shrink), &[4., 6.25, 1., 0.25]));
+        assert_eq!(min_filter(&samples, 3, Boundary::Nearest), vec![-3., -3., -3., -1., -1.]);
+        assert_eq!(max_filter(&samples, 3, Boundary::Nearest), vec![1., 2., 2., 2., 0.]);
+        assert_eq!(peak_filter(&samples, 3, Boundary::Constant(-4.)), vec![4., 3., 3., 2., 4.]);
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let samples = vec![1., 5., 2., 8., 3.];
+        assert_eq!(median_filter(&samples, 5, Boundary::Reflect), vec![2., 2., 3., 3., 3.]);
+        assert_eq!(median_filter(&samples, 5, Boundary::Nearest), vec![1., 2., 3., 3., 3.]);
+        assert_eq!(median_filter(&samples, 3, Boundary::Constant(10.)), vec![5., 2., 5., 3., 8.]);
+        // every window of a periodic signal as long as its period has the same mean
+        assert!(mean_filter(&samples, 5, Boundary::Wrap).iter().all(|x| (x - 3.8).abs() < 1e-6));
+        let expected = [7. / 3., 8. / 3., 5., 13. / 3., 14. / 3.];
+        let computed = mean_filter(&samples, 3, Boundary::Reflect);
+        assert!(computed.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+
+        // signals shorter than the window
+        assert_eq!(median_filter(&[1., 2.], 5, Boundary::Reflect), vec![2., 1.]);
+        assert!(median_filter(&[1., 2.], 3, Boundary::Shrink).is_empty());
+        assert!(mean_filter(&[1., 2.], 3, Boundary::Shrink).is_empty());
+        assert!(mean_filter(&[], 3, Boundary::Nearest).is_empty());
+    }
+
+    #[test]
+    fn test_streaming_filters_match_offline() {
+        let samples = vec![3., 2., 4., 5., 1., 2., 3., 4., 5., 6., 3., 2., 1.];
+
+        let mut streamed = vec![samples.clone()];
+        process_offline(&mut MedianFilter::new(3), &mut streamed, 44100, 4);
+        // once the window is full, the streamed output matches the offline output without padding
+        assert_eq!(streamed[0][2..].to_vec(), median_filter(&samples, 3, Boundary::Shrink));
+
+        let mut streamed = vec![samples.clone()];
+        process_offline(&mut MeanFilter::new(3), &mut streamed, 44100, 5);
+        let expected = mean_filter(&samples, 3, Boundary::Shrink);
+        assert!(streamed[0][2..].iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
 }
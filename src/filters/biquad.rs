@@ -0,0 +1,258 @@
+use std::f64::consts::PI;
+
+use rustfft::num_complex::Complex;
+
+use crate::processor::Processor;
+
+/// The filter shapes from Robert Bristow-Johnson's Audio EQ Cookbook. The shelves and the peaking filter
+/// boost or cut by `gain_db` decibels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BiquadShape {
+    LowPass,
+    HighPass,
+    /// Band-pass with a peak gain of 0 dB at the center frequency.
+    BandPass,
+    Notch,
+    AllPass,
+    Peaking { gain_db: f32 },
+    LowShelf { gain_db: f32 },
+    HighShelf { gain_db: f32 },
+}
+
+/// Coefficients of a second order section, normalized so that `a0` is 1:
+/// `y[n] = b0 x[n] + b1 x[n-1] + b2 x[n-2] - a1 y[n-1] - a2 y[n-2]`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl BiquadCoefficients {
+    /// Designs a filter with its corner or center at `frequency` Hz; `q` sets the bandwidth, or the slope of the
+    /// shelves, where a `q` of `FRAC_1_SQRT_2` gives Butterworth low and high-passes.
+    pub fn design(shape: BiquadShape, frequency: f32, q: f32, sample_rate: u32) -> BiquadCoefficients {
+        assert!(
+            0. < frequency && frequency < sample_rate as f32 / 2.,
+            "Filter frequency must be between 0 and the Nyquist frequency."
+        );
+        assert!(q > 0., "Filter Q must be positive.");
+        let w0 = 2. * PI * frequency as f64 / sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2. * q as f64);
+        let gain = |gain_db: f32| 10f64.powf(gain_db as f64 / 40.);
+
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            BiquadShape::LowPass => ((1. - cos) / 2., 1. - cos, (1. - cos) / 2., 1. + alpha, -2. * cos, 1. - alpha),
+            BiquadShape::HighPass => {
+                ((1. + cos) / 2., -(1. + cos), (1. + cos) / 2., 1. + alpha, -2. * cos, 1. - alpha)
+            }
+            BiquadShape::BandPass => (alpha, 0., -alpha, 1. + alpha, -2. * cos, 1. - alpha),
+            BiquadShape::Notch => (1., -2. * cos, 1., 1. + alpha, -2. * cos, 1. - alpha),
+            BiquadShape::AllPass => (1. - alpha, -2. * cos, 1. + alpha, 1. + alpha, -2. * cos, 1. - alpha),
+            BiquadShape::Peaking { gain_db } => {
+                let a = gain(gain_db);
+                (1. + alpha * a, -2. * cos, 1. - alpha * a, 1. + alpha / a, -2. * cos, 1. - alpha / a)
+            }
+            BiquadShape::LowShelf { gain_db } => {
+                let a = gain(gain_db);
+                let k = 2. * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.) - (a - 1.) * cos + k),
+                    2. * a * ((a - 1.) - (a + 1.) * cos),
+                    a * ((a + 1.) - (a - 1.) * cos - k),
+                    (a + 1.) + (a - 1.) * cos + k,
+                    -2. * ((a - 1.) + (a + 1.) * cos),
+                    (a + 1.) + (a - 1.) * cos - k,
+                )
+            }
+            BiquadShape::HighShelf { gain_db } => {
+                let a = gain(gain_db);
+                let k = 2. * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.) + (a - 1.) * cos + k),
+                    -2. * a * ((a - 1.) + (a + 1.) * cos),
+                    a * ((a + 1.) + (a - 1.) * cos - k),
+                    (a + 1.) - (a - 1.) * cos + k,
+                    2. * ((a - 1.) - (a + 1.) * cos),
+                    (a + 1.) - (a - 1.) * cos - k,
+                )
+            }
+        };
+        BiquadCoefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// The complex frequency response at `frequency` Hz.
+    pub fn response(&self, frequency: f64, sample_rate: u32) -> Complex<f64> {
+        let z1 = Complex::from_polar(1., -2. * PI * frequency / sample_rate as f64);
+        let z2 = z1 * z1;
+        (self.b0 + z1 * self.b1 + z2 * self.b2) / (1. + z1 * self.a1 + z2 * self.a2)
+    }
+
+    /// Filters one sample in transposed direct form II, which only needs two state variables per channel and
+    /// keeps rounding noise low for the low frequencies that audio filters are usually tuned to.
+    #[inline]
+    pub fn process_sample(&self, x: f64, state: &mut [f64; 2]) -> f64 {
+        let y = self.b0 * x + state[0];
+        state[0] = self.b1 * x - self.a1 * y + state[1];
+        state[1] = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// A biquad filter designed from a shape, frequency and Q, which is redesigned for the sample rate that it's
+/// prepared with and keeps separate state for each channel.
+pub struct Biquad {
+    shape: BiquadShape,
+    frequency: f32,
+    q: f32,
+    coefficients: Option<BiquadCoefficients>,
+    states: Vec<[f64; 2]>,
+}
+
+impl Biquad {
+    pub fn new(shape: BiquadShape, frequency: f32, q: f32) -> Biquad {
+        Biquad {
+            shape,
+            frequency,
+            q,
+            coefficients: None,
+            states: Vec::new(),
+        }
+    }
+
+    /// The coefficients for the sample rate that the filter was last prepared with.
+    pub fn coefficients(&self) -> Option<BiquadCoefficients> {
+        self.coefficients
+    }
+}
+
+impl Processor for Biquad {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize) {
+        self.coefficients = Some(BiquadCoefficients::design(self.shape, self.frequency, self.q, sample_rate));
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        let coefficients = self.coefficients.expect("Biquad::prepare must be called before process.");
+        if self.states.len() < channels.len() {
+            self.states.resize(channels.len(), [0.; 2]);
+        }
+        for (channel, state) in channels.iter_mut().zip(self.states.iter_mut()) {
+            for s in channel.iter_mut() {
+                *s = coefficients.process_sample(*s as f64, state) as f32;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.states.iter_mut().for_each(|s| *s = [0.; 2]);
+    }
+}
+
+/// A cascade of second order sections, such as the filters designed by `iir::design`, with separate state for
+/// each channel.
+pub struct BiquadCascade {
+    sections: Vec<BiquadCoefficients>,
+    states: Vec<Vec<[f64; 2]>>,
+}
+
+impl BiquadCascade {
+    pub fn new(sections: Vec<BiquadCoefficients>) -> BiquadCascade {
+        BiquadCascade {
+            sections,
+            states: Vec::new(),
+        }
+    }
+
+    pub fn sections(&self) -> &[BiquadCoefficients] {
+        &self.sections
+    }
+
+    /// The complex frequency response at `frequency` Hz, which is the product of the sections' responses.
+    pub fn response(&self, frequency: f64, sample_rate: u32) -> Complex<f64> {
+        self.sections
+            .iter()
+            .map(|s| s.response(frequency, sample_rate))
+            .fold(Complex::new(1., 0.), |a, b| a * b)
+    }
+}
+
+impl Processor for BiquadCascade {
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {
+        self.reset();
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        if self.states.len() < channels.len() {
+            self.states.resize(channels.len(), vec![[0.; 2]; self.sections.len()]);
+        }
+        for (channel, states) in channels.iter_mut().zip(self.states.iter_mut()) {
+            for s in channel.iter_mut() {
+                *s = self
+                    .sections
+                    .iter()
+                    .zip(states.iter_mut())
+                    .fold(*s as f64, |x, (section, state)| section.process_sample(x, state)) as f32;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.states.iter_mut().flatten().for_each(|s| *s = [0.; 2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use crate::processor::process_offline;
+
+    use super::{Biquad, BiquadCoefficients, BiquadShape};
+
+    fn magnitude_db(shape: BiquadShape, frequency: f64) -> f64 {
+        let coefficients = BiquadCoefficients::design(shape, 1000., FRAC_1_SQRT_2, 48000);
+        20. * coefficients.response(frequency, 48000).norm().log10()
+    }
+
+    #[test]
+    fn test_design() {
+        let near = |a: f64, b: f64| (a - b).abs() < 0.01;
+        assert!(near(magnitude_db(BiquadShape::LowPass, 0.), 0.));
+        assert!(near(magnitude_db(BiquadShape::LowPass, 1000.), -3.0103));
+        assert!(magnitude_db(BiquadShape::LowPass, 10000.) < -35.);
+        assert!(near(magnitude_db(BiquadShape::HighPass, 24000.), 0.));
+        assert!(near(magnitude_db(BiquadShape::HighPass, 1000.), -3.0103));
+        assert!(near(magnitude_db(BiquadShape::BandPass, 1000.), 0.));
+        assert!(magnitude_db(BiquadShape::Notch, 1000.) < -100.);
+        assert!(near(magnitude_db(BiquadShape::AllPass, 300.), 0.));
+        assert!(near(magnitude_db(BiquadShape::AllPass, 5000.), 0.));
+        assert!(near(magnitude_db(BiquadShape::Peaking { gain_db: 6. }, 1000.), 6.));
+        assert!(near(magnitude_db(BiquadShape::Peaking { gain_db: -6. }, 20000.), 0.));
+        assert!(near(magnitude_db(BiquadShape::LowShelf { gain_db: -9. }, 0.), -9.));
+        assert!(near(magnitude_db(BiquadShape::LowShelf { gain_db: -9. }, 1000.), -4.5));
+        assert!(near(magnitude_db(BiquadShape::HighShelf { gain_db: 4. }, 24000.), 4.));
+    }
+
+    #[test]
+    fn test_biquad_filters_each_channel() {
+        // a 200 Hz tone passes through a 2 kHz low-pass, and a 10 kHz one is mostly removed
+        let tone = |frequency: f32| -> Vec<f32> {
+            (0..4800).map(|i| (2. * std::f32::consts::PI * frequency * i as f32 / 48000.).sin()).collect()
+        };
+        let mut samples = vec![tone(200.), tone(10000.)];
+        process_offline(&mut Biquad::new(BiquadShape::LowPass, 2000., FRAC_1_SQRT_2), &mut samples, 48000, 64);
+        let peak = |xs: &[f32]| xs.iter().fold(0., |a: f32, &b| a.max(b.abs()));
+        assert!((peak(&samples[0][2400..]) - 1.).abs() < 0.02);
+        assert!(peak(&samples[1][2400..]) < 0.05);
+    }
+}
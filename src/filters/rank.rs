@@ -0,0 +1,208 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+// a sample with its position in the stream, ordered by value and then position, which makes every entry in the
+// heaps distinct; values are ordered with `total_cmp`, so NaNs sort above infinity, or below minus infinity when
+// their sign bit is set, rather than breaking the ordering
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    value: f32,
+    index: usize,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        self.value.total_cmp(&other.value).then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+/// The sample of a given rank in a sliding window, such as the median, in O(log k) time per sample for a window
+/// of k samples. The `rank + 1` smallest samples are kept in a max-heap and the rest in a min-heap, so the top of
+/// the first heap is the answer. Samples that leave the window are only removed from the heaps once they reach
+/// the top, or when the heaps grow to twice the window.
+pub struct RunningRank {
+    len: usize,
+    rank: usize,
+    window: Vec<Entry>,
+    pushed: usize,
+    low: BinaryHeap<Entry>,
+    high: BinaryHeap<Reverse<Entry>>,
+    // how many of the heaps' entries are still in the window
+    low_len: usize,
+    high_len: usize,
+}
+
+impl RunningRank {
+    /// Tracks the sample of `rank` in windows of `len` samples, where rank 0 is the smallest.
+    pub fn new(len: usize, rank: usize) -> RunningRank {
+        assert!(len > 0, "Window length must be nonzero.");
+        assert!(rank < len, "Rank must be less than the window length.");
+        RunningRank {
+            len,
+            rank,
+            window: Vec::with_capacity(len),
+            pushed: 0,
+            low: BinaryHeap::with_capacity(2 * len),
+            high: BinaryHeap::with_capacity(2 * len),
+            low_len: 0,
+            high_len: 0,
+        }
+    }
+
+    /// Tracks a percentile from 0 to 100 in windows of `len` samples, rounding to the nearest rank.
+    pub fn percentile(len: usize, percentile: f64) -> RunningRank {
+        assert!((0. ..=100.).contains(&percentile), "Percentile must be between 0 and 100.");
+        RunningRank::new(len, ((len - 1) as f64 * percentile / 100.).round() as usize)
+    }
+
+    pub fn window_len(&self) -> usize {
+        self.len
+    }
+
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// Empties the window.
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.pushed = 0;
+        self.low.clear();
+        self.high.clear();
+        self.low_len = 0;
+        self.high_len = 0;
+    }
+
+    // whether an entry has left the window
+    fn expired(&self, entry: &Entry) -> bool {
+        entry.index + self.len < self.pushed
+    }
+
+    fn low_top(&mut self) -> Option<Entry> {
+        while let Some(&top) = self.low.peek() {
+            if !self.expired(&top) {
+                return Some(top);
+            }
+            self.low.pop();
+        }
+        None
+    }
+
+    fn high_top(&mut self) -> Option<Entry> {
+        while let Some(&Reverse(top)) = self.high.peek() {
+            if !self.expired(&top) {
+                return Some(top);
+            }
+            self.high.pop();
+        }
+        None
+    }
+
+    /// Slides the window along by one sample, and returns the sample of the tracked rank once the window is full.
+    pub fn push(&mut self, value: f32) -> Option<f32> {
+        let entry = Entry {
+            value,
+            index: self.pushed,
+        };
+        let slot = self.pushed % self.len;
+        self.pushed += 1;
+        if self.window.len() < self.len {
+            self.window.push(entry);
+        } else {
+            // the oldest entry is now expired, and is on the low side if it's no larger than the low side's top
+            let oldest = std::mem::replace(&mut self.window[slot], entry);
+            if self.low.peek().is_some_and(|top| oldest <= *top) {
+                self.low_len -= 1;
+            } else {
+                self.high_len -= 1;
+            }
+        }
+
+        if self.low_top().is_some_and(|top| entry < top) {
+            self.low.push(entry);
+            self.low_len += 1;
+        } else {
+            self.high.push(Reverse(entry));
+            self.high_len += 1;
+        }
+
+        let target = (self.rank + 1).min(self.window.len());
+        while self.low_len > target {
+            let top = self.low_top().unwrap();
+            self.low.pop();
+            self.high.push(Reverse(top));
+            self.low_len -= 1;
+            self.high_len += 1;
+        }
+        while self.low_len < target {
+            let top = self.high_top().unwrap();
+            self.high.pop();
+            self.low.push(top);
+            self.high_len -= 1;
+            self.low_len += 1;
+        }
+
+        if self.low.len() + self.high.len() > 2 * self.len {
+            let pushed = self.pushed;
+            let len = self.len;
+            self.low.retain(|e| e.index + len >= pushed);
+            self.high.retain(|Reverse(e)| e.index + len >= pushed);
+        }
+
+        if self.window.len() == self.len {
+            self.low_top().map(|top| top.value)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::RunningRank;
+
+    #[test]
+    fn test_matches_sorting() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // few distinct values, so that there are plenty of ties
+        let samples: Vec<f32> = (0..2000).map(|_| rng.gen_range(0..20) as f32).collect();
+        for &(len, rank) in &[(1, 0), (2, 1), (7, 3), (16, 0), (16, 15), (31, 20)] {
+            let mut running = RunningRank::new(len, rank);
+            for (i, &x) in samples.iter().enumerate() {
+                let computed = running.push(x);
+                if i + 1 < len {
+                    assert_eq!(computed, None);
+                } else {
+                    let mut window = samples[i + 1 - len..=i].to_vec();
+                    window.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    assert_eq!(computed, Some(window[rank]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_nan() {
+        let mut running = RunningRank::new(3, 1);
+        let computed: Vec<Option<f32>> = [1., f32::NAN, 2., 3., 4.].iter().map(|&x| running.push(x)).collect();
+        assert_eq!(computed[..2], [None, None]);
+        // NaN sorts above everything else, so the median skips over it
+        assert_eq!(computed[2..], [Some(2.), Some(3.), Some(3.)]);
+    }
+}
@@ -0,0 +1,108 @@
+/// The mean and variance of a sliding window, updated in O(1) time per sample with Welford's method. Adding and
+/// removing samples leaves rounding errors behind which would build up over a long signal, so the moments are
+/// recomputed from the window each time it has been replaced, which bounds the error by that of one window.
+pub struct RunningMoments {
+    window: Vec<f64>,
+    len: usize,
+    position: usize,
+    mean: f64,
+    // the sum of squared differences from the mean
+    m2: f64,
+}
+
+impl RunningMoments {
+    pub fn new(len: usize) -> RunningMoments {
+        assert!(len > 0, "Window length must be nonzero.");
+        RunningMoments {
+            window: Vec::with_capacity(len),
+            len,
+            position: 0,
+            mean: 0.,
+            m2: 0.,
+        }
+    }
+
+    pub fn window_len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.window.len() == self.len
+    }
+
+    /// Empties the window.
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.position = 0;
+        self.mean = 0.;
+        self.m2 = 0.;
+    }
+
+    /// Slides the window along by one sample. Until the window is full, the moments are of the samples so far.
+    pub fn push(&mut self, value: f32) {
+        let value = value as f64;
+        if !self.is_full() {
+            self.window.push(value);
+            let delta = value - self.mean;
+            self.mean += delta / self.window.len() as f64;
+            self.m2 += delta * (value - self.mean);
+        } else {
+            let oldest = std::mem::replace(&mut self.window[self.position], value);
+            let old_mean = self.mean;
+            self.mean += (value - oldest) / self.len as f64;
+            self.m2 += (value - oldest) * (value - self.mean + oldest - old_mean);
+        }
+        self.position = (self.position + 1) % self.len;
+        if self.position == 0 {
+            self.resum();
+        }
+    }
+
+    fn resum(&mut self) {
+        self.mean = self.window.iter().sum::<f64>() / self.window.len() as f64;
+        self.m2 = self.window.iter().map(|x| (x - self.mean).powi(2)).sum();
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// The population variance, dividing by the window length.
+    pub fn variance(&self) -> f64 {
+        if self.window.is_empty() {
+            0.
+        } else {
+            self.m2.max(0.) / self.window.len() as f64
+        }
+    }
+
+    pub fn standard_deviation(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// The root mean square, which is the mean's and standard deviation's magnitude together.
+    pub fn rms(&self) -> f64 {
+        (self.mean * self.mean + self.variance()).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunningMoments;
+
+    #[test]
+    fn test_running_moments_dont_drift() {
+        // a small signal on a large offset, where a running total loses the signal's detail and the variance from
+        // the sum of squares cancels badly
+        let signal: Vec<f32> = (0..1_000_003usize).map(|i| 1000. + ((i * 7919) % 101) as f32 / 100.).collect();
+        let mut moments = RunningMoments::new(100);
+        signal.iter().for_each(|&x| moments.push(x));
+
+        let window: Vec<f64> = signal[signal.len() - 100..].iter().map(|&x| x as f64).collect();
+        let mean = window.iter().sum::<f64>() / 100.;
+        let variance = window.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 100.;
+        assert!((moments.mean() - mean).abs() < 1e-9);
+        assert!((moments.variance() - variance).abs() < 1e-9 * variance);
+        assert!((moments.rms() - (mean * mean + variance).sqrt()).abs() < 1e-9);
+    }
+}
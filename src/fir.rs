@@ -0,0 +1,454 @@
+use std::f64::consts::PI;
+
+use num::Zero;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::iir::Passband;
+use crate::resample::{kaiser, sinc};
+
+// grid points per cosine in the Parks-McClellan frequency grid
+const GRID_DENSITY: usize = 16;
+const MAX_ITERATIONS: usize = 100;
+// how much longer than the kernel the FFTs of the minimum phase conversion are, which keeps the cepstrum from
+// aliasing
+const CEPSTRUM_OVERSAMPLING: usize = 64;
+// the quietest part of a spectrum that the minimum phase conversion takes the log of, relative to its peak
+const MAGNITUDE_FLOOR: f64 = 1e-9;
+
+/// Windows for shaping a windowed-sinc filter. Wider main lobes give wider transition bands but lower side lobes,
+/// and so less ripple and more attenuation in the stopband.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Window {
+    /// No window, which gives the narrowest transition band but only about 21 dB of attenuation.
+    Rectangular,
+    /// About 44 dB of attenuation.
+    Hann,
+    /// About 75 dB of attenuation, with a transition band three times as wide as a rectangular window's.
+    Blackman,
+    /// Trades transition width for attenuation with `beta`; see `Window::kaiser`.
+    Kaiser { beta: f64 },
+}
+
+impl Window {
+    /// The Kaiser window that gives about `attenuation_db` decibels of stopband attenuation.
+    pub fn kaiser(attenuation_db: f64) -> Window {
+        let beta = if attenuation_db > 50. {
+            0.1102 * (attenuation_db - 8.7)
+        } else if attenuation_db >= 21. {
+            0.5842 * (attenuation_db - 21.).powf(0.4) + 0.07886 * (attenuation_db - 21.)
+        } else {
+            0.
+        };
+        Window::Kaiser { beta }
+    }
+
+    /// The window's `len` coefficients, which are symmetric.
+    pub fn coefficients(&self, len: usize) -> Vec<f64> {
+        if len == 1 {
+            return vec![1.];
+        }
+        let last = (len - 1) as f64;
+        (0..len)
+            .map(|n| {
+                let phase = 2. * PI * n as f64 / last;
+                match *self {
+                    Window::Rectangular => 1.,
+                    Window::Hann => 0.5 - 0.5 * phase.cos(),
+                    Window::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2. * phase).cos(),
+                    Window::Kaiser { beta } => kaiser(2. * n as f64 / last - 1., beta),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The odd length of a Kaiser windowed-sinc filter with about `attenuation_db` decibels of stopband attenuation
+/// and a transition band `transition_width` Hz wide, using Kaiser's formula.
+pub fn kaiser_len(attenuation_db: f64, transition_width: f64, sample_rate: u32) -> usize {
+    assert!(transition_width > 0., "Transition width must be positive.");
+    let width = 2. * PI * transition_width / sample_rate as f64;
+    let len = ((attenuation_db - 7.95) / (2.285 * width)).ceil().max(0.) as usize + 1;
+    len | 1
+}
+
+/// The complex frequency response of `kernel` at `frequency` Hz.
+pub fn response(kernel: &[f32], frequency: f64, sample_rate: u32) -> Complex<f64> {
+    let omega = -2. * PI * frequency / sample_rate as f64;
+    kernel.iter().enumerate().map(|(n, &h)| Complex::from_polar(h as f64, omega * n as f64)).sum()
+}
+
+/// Designs a linear phase filter `len` samples long by windowing the ideal filter's impulse response. The
+/// cutoffs are where the response is 6 dB down, and the passband is scaled to unit gain at DC for low-passes
+/// and band-stops, at the Nyquist frequency for high-passes and at the center of the band for band-passes.
+/// High-passes and band-stops need an odd length, as even lengths always have a zero at the Nyquist frequency.
+/// The kernel can be used with `convolution::convolve` or a `Convolver`, and is delayed by `(len - 1) / 2`
+/// samples, which `Mode::Same` takes back out.
+pub fn windowed_sinc(band: Passband, len: usize, window: Window, sample_rate: u32) -> Vec<f32> {
+    assert!(len > 0, "Filter length must be nonzero.");
+    let fs = sample_rate as f64;
+    let check = |frequency: f64| {
+        assert!(
+            0. < frequency && frequency < fs / 2.,
+            "Filter frequencies must be between 0 and the Nyquist frequency."
+        );
+        frequency / fs
+    };
+    if let Passband::HighPass(_) | Passband::BandStop(..) = band {
+        assert!(len % 2 == 1, "High-pass and band-stop filters must have an odd length.");
+    }
+    let center = (len - 1) as f64 / 2.;
+    // ideal low-pass with a cutoff of `cutoff` times the sample rate, where 0.5 is an all-pass
+    let low_pass = |cutoff: f64, n: usize| 2. * cutoff * sinc(2. * cutoff * (n as f64 - center));
+
+    let (ideal, normalize_at): (Box<dyn Fn(usize) -> f64>, f64) = match band {
+        Passband::LowPass(cutoff) => {
+            let cutoff = check(cutoff);
+            (Box::new(move |n| low_pass(cutoff, n)), 0.)
+        }
+        Passband::HighPass(cutoff) => {
+            let cutoff = check(cutoff);
+            (Box::new(move |n| low_pass(0.5, n) - low_pass(cutoff, n)), fs / 2.)
+        }
+        Passband::BandPass(low, high) | Passband::BandStop(low, high) => {
+            assert!(low < high, "Band edges must be increasing.");
+            let (low, high) = (check(low), check(high));
+            if let Passband::BandPass(..) = band {
+                (Box::new(move |n| low_pass(high, n) - low_pass(low, n)), (low + high) / 2. * fs)
+            } else {
+                (Box::new(move |n| low_pass(0.5, n) - low_pass(high, n) + low_pass(low, n)), 0.)
+            }
+        }
+    };
+    let kernel: Vec<f32> = window
+        .coefficients(len)
+        .iter()
+        .enumerate()
+        .map(|(n, w)| (ideal(n) * w) as f32)
+        .collect();
+    let gain = response(&kernel, normalize_at, sample_rate).norm() as f32;
+    kernel.iter().map(|h| h / gain).collect()
+}
+
+/// A band of a Parks-McClellan filter, from `start` to `end` Hz, where the response should be `gain`. Errors are
+/// scaled by `weight`, so a band with twice the weight of another has half its ripple.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Band {
+    pub start: f64,
+    pub end: f64,
+    pub gain: f64,
+    pub weight: f64,
+}
+
+struct GridPoint {
+    x: f64,
+    desired: f64,
+    weight: f64,
+    band: usize,
+}
+
+// the polynomial in cos(w) that alternates about the desired response with an error of `delta` at the extremal
+// frequencies, evaluated by barycentric interpolation through them
+struct Alternation {
+    x: Vec<f64>,
+    weights: Vec<f64>,
+    values: Vec<f64>,
+    delta: f64,
+}
+
+impl Alternation {
+    fn new(grid: &[GridPoint], extremals: &[usize]) -> Alternation {
+        let x: Vec<f64> = extremals.iter().map(|&i| grid[i].x).collect();
+        // the barycentric weights are products of many differences, so work with their logs to keep them in range
+        let (signs, logs): (Vec<f64>, Vec<f64>) = x
+            .iter()
+            .enumerate()
+            .map(|(k, xk)| {
+                x.iter().enumerate().filter(|&(j, _)| j != k).fold((1., 0.), |(sign, log), (_, xj)| {
+                    let difference: f64 = xk - xj;
+                    (sign * difference.signum(), log - difference.abs().ln())
+                })
+            })
+            .unzip();
+        let mean = logs.iter().sum::<f64>() / logs.len() as f64;
+        let weights: Vec<f64> = signs.iter().zip(&logs).map(|(sign, log)| sign * (log - mean).exp()).collect();
+
+        let alternating = |k: usize| if k % 2 == 0 { 1. } else { -1. };
+        let point = |k: usize| &grid[extremals[k]];
+        let numerator: f64 = weights.iter().enumerate().map(|(k, w)| w * point(k).desired).sum();
+        let denominator: f64 = weights.iter().enumerate().map(|(k, w)| w * alternating(k) / point(k).weight).sum();
+        let delta = numerator / denominator;
+        let values = (0..x.len()).map(|k| point(k).desired - alternating(k) * delta / point(k).weight).collect();
+        Alternation {
+            x,
+            weights,
+            values,
+            delta,
+        }
+    }
+
+    fn evaluate(&self, x: f64) -> f64 {
+        let mut numerator = 0.;
+        let mut denominator = 0.;
+        for ((xk, w), value) in self.x.iter().zip(&self.weights).zip(&self.values) {
+            let difference = x - xk;
+            if difference.abs() < 1e-14 {
+                return *value;
+            }
+            numerator += w / difference * value;
+            denominator += w / difference;
+        }
+        numerator / denominator
+    }
+}
+
+// the local extrema of the error that are at least `min_error` in size, with alternating signs, keeping the
+// largest of any run with the same sign and then trimming the smaller end until there are at most `count`
+fn find_extremals(grid: &[GridPoint], errors: &[f64], min_error: f64, count: usize) -> Vec<usize> {
+    let mut extremals: Vec<usize> = Vec::new();
+    for i in 0..grid.len() {
+        let sign = errors[i].signum();
+        let neighbour_below = |j: usize| grid[j].band != grid[i].band || sign * errors[j] <= sign * errors[i];
+        let is_extremum = (i == 0 || neighbour_below(i - 1)) && (i + 1 == grid.len() || neighbour_below(i + 1));
+        if !is_extremum || errors[i].abs() < min_error * (1. - 1e-9) {
+            continue;
+        }
+        match extremals.last_mut() {
+            Some(last) if errors[*last].signum() == sign => {
+                if errors[i].abs() > errors[*last].abs() {
+                    *last = i;
+                }
+            }
+            _ => extremals.push(i),
+        }
+    }
+    while extremals.len() > count {
+        if errors[extremals[0]].abs() < errors[extremals[extremals.len() - 1]].abs() {
+            extremals.remove(0);
+        } else {
+            extremals.pop();
+        }
+    }
+    extremals
+}
+
+/// Designs a linear phase filter `len` samples long whose response is equiripple: the Remez exchange algorithm
+/// finds the filter with the smallest largest weighted error from the bands' gains. Frequencies between the
+/// bands are left free as transition bands. Even lengths always have a zero at the Nyquist frequency, so they
+/// can't have a band there with a nonzero gain.
+pub fn parks_mcclellan(len: usize, bands: &[Band], sample_rate: u32) -> Vec<f32> {
+    assert!(len > 2, "Filter length must be at least 3.");
+    assert!(!bands.is_empty(), "There must be at least one band.");
+    let nyquist = sample_rate as f64 / 2.;
+    for (i, band) in bands.iter().enumerate() {
+        let after_previous = i == 0 || bands[i - 1].end < band.start;
+        assert!(
+            0. <= band.start && band.start < band.end && band.end <= nyquist && after_previous,
+            "Band edges must be increasing and between 0 and the Nyquist frequency."
+        );
+        assert!(band.weight > 0., "Band weights must be positive.");
+    }
+    let odd = len % 2 == 1;
+    let last = bands[bands.len() - 1];
+    assert!(
+        odd || last.end < nyquist || last.gain == 0.,
+        "Even length filters can't have a nonzero gain at the Nyquist frequency."
+    );
+
+    // the zero phase response is a sum of `n_cosines` cosines, and for even lengths it's also multiplied by
+    // cos(w / 2), which the desired response and weights are scaled to make up for
+    let n_cosines = if odd { len / 2 + 1 } else { len / 2 };
+    let factor = |omega: f64| if odd { 1. } else { (omega / 2.).cos() };
+    let spacing = PI / (GRID_DENSITY * n_cosines) as f64;
+    let mut grid = Vec::new();
+    for (index, band) in bands.iter().enumerate() {
+        let (start, end) = (PI * band.start / nyquist, PI * band.end / nyquist);
+        let n_points = ((end - start) / spacing).ceil().max(1.) as usize;
+        for i in 0..=n_points {
+            let omega = start + (end - start) * i as f64 / n_points as f64;
+            let factor = factor(omega);
+            if factor > 1e-6 {
+                grid.push(GridPoint {
+                    x: omega.cos(),
+                    desired: band.gain / factor,
+                    weight: band.weight * factor,
+                    band: index,
+                });
+            }
+        }
+    }
+
+    let n_extremals = n_cosines + 1;
+    assert!(grid.len() >= n_extremals, "Bands are too narrow for the filter length.");
+    let mut extremals: Vec<usize> = (0..n_extremals).map(|k| k * (grid.len() - 1) / (n_extremals - 1)).collect();
+    let mut alternation = Alternation::new(&grid, &extremals);
+    for _ in 0..MAX_ITERATIONS {
+        let errors: Vec<f64> = grid.iter().map(|p| p.weight * (p.desired - alternation.evaluate(p.x))).collect();
+        let max_error = errors.iter().fold(0., |a: f64, b| a.max(b.abs()));
+        let next = find_extremals(&grid, &errors, alternation.delta.abs(), n_extremals);
+        if max_error - alternation.delta.abs() <= 1e-6 * max_error || next.len() < n_extremals {
+            break;
+        }
+        extremals = next;
+        alternation = Alternation::new(&grid, &extremals);
+    }
+
+    // sample the zero phase response at `len` frequencies around the circle, and take the inverse DFT, delayed
+    // by half the length to make it causal
+    let frequencies: Vec<(f64, f64)> = (0..len)
+        .map(|j| {
+            let omega = 2. * PI * j as f64 / len as f64;
+            (omega, factor(omega) * alternation.evaluate(omega.cos()))
+        })
+        .collect();
+    let center = (len - 1) as f64 / 2.;
+    (0..len)
+        .map(|n| {
+            let sum: f64 = frequencies.iter().map(|(omega, a)| a * (omega * (n as f64 - center)).cos()).sum();
+            (sum / len as f64) as f32
+        })
+        .collect()
+}
+
+/// Converts a filter into the minimum phase filter with the same magnitude response, using the real cepstrum.
+/// Minimum phase filters have as little delay as a causal filter with their response can, with no pre-ringing,
+/// at the cost of a phase response that's no longer linear.
+pub fn minimum_phase(kernel: &[f32]) -> Vec<f32> {
+    if kernel.is_empty() {
+        return Vec::new();
+    }
+    let buf_len = (kernel.len() * CEPSTRUM_OVERSAMPLING).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(buf_len);
+    let ifft = planner.plan_fft_inverse(buf_len);
+
+    let mut buffer: Vec<Complex<f64>> = kernel
+        .iter()
+        .map(|&x| Complex::new(x as f64, 0.))
+        .chain(std::iter::repeat(Complex::zero()))
+        .take(buf_len)
+        .collect();
+    fft.process(&mut buffer);
+    let floor = buffer.iter().fold(0., |a: f64, b| a.max(b.norm())) * MAGNITUDE_FLOOR;
+    buffer.iter_mut().for_each(|x| *x = Complex::new(x.norm().max(floor).ln(), 0.));
+    ifft.process(&mut buffer);
+
+    // folding the negative quefrencies onto the positive ones keeps the log magnitude and makes the phase its
+    // Hilbert transform, which is the minimum phase
+    let half = buf_len / 2;
+    for (i, x) in buffer.iter_mut().enumerate() {
+        let fold = match i {
+            0 => 1.,
+            i if i == half => 1.,
+            i if i < half => 2.,
+            _ => 0.,
+        };
+        *x *= fold / buf_len as f64;
+    }
+    fft.process(&mut buffer);
+    buffer.iter_mut().for_each(|x| *x = x.exp());
+    ifft.process(&mut buffer);
+    buffer[..kernel.len()].iter().map(|x| (x.re / buf_len as f64) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use rustfft::FftPlanner;
+
+    use crate::convolution::{convolve, Convolver, Mode};
+    use crate::iir::Passband;
+
+    use super::{kaiser_len, minimum_phase, parks_mcclellan, response, windowed_sinc, Band, Window};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn magnitude_db(kernel: &[f32], frequency: f64) -> f64 {
+        20. * response(kernel, frequency, SAMPLE_RATE).norm().log10()
+    }
+
+    // the largest magnitude in decibels over a range of frequencies
+    fn max_db(kernel: &[f32], start: f64, end: f64) -> f64 {
+        (0..=200).map(|i| magnitude_db(kernel, start + (end - start) * i as f64 / 200.)).fold(f64::MIN, f64::max)
+    }
+
+    #[test]
+    fn test_windowed_sinc() {
+        let len = kaiser_len(60., 1000., SAMPLE_RATE);
+        let low_pass = windowed_sinc(Passband::LowPass(4000.), len, Window::kaiser(60.), SAMPLE_RATE);
+        assert!(magnitude_db(&low_pass, 0.).abs() < 1e-4);
+        assert!((magnitude_db(&low_pass, 4000.) + 6.02).abs() < 0.1);
+        assert!(max_db(&low_pass, 0., 3500.) < 0.02);
+        assert!(max_db(&low_pass, 4500., 24000.) < -59.);
+
+        // the windows' side lobes set how much they attenuate
+        for &(window, attenuation) in &[(Window::Rectangular, 20.), (Window::Hann, 43.), (Window::Blackman, 73.)] {
+            let kernel = windowed_sinc(Passband::LowPass(4000.), 201, window, SAMPLE_RATE);
+            assert!(max_db(&kernel, 6000., 24000.) < -attenuation);
+        }
+
+        let high_pass = windowed_sinc(Passband::HighPass(4000.), len, Window::kaiser(60.), SAMPLE_RATE);
+        assert!(max_db(&high_pass, 0., 3500.) < -59.);
+        assert!(magnitude_db(&high_pass, 10000.).abs() < 0.02);
+        let band_pass = windowed_sinc(Passband::BandPass(4000., 8000.), len, Window::kaiser(60.), SAMPLE_RATE);
+        assert!(max_db(&band_pass, 0., 3500.) < -59.);
+        assert!(magnitude_db(&band_pass, 6000.).abs() < 0.02);
+        assert!(max_db(&band_pass, 8500., 24000.) < -59.);
+        let band_stop = windowed_sinc(Passband::BandStop(4000., 8000.), len, Window::kaiser(60.), SAMPLE_RATE);
+        assert!(magnitude_db(&band_stop, 1000.).abs() < 0.02);
+        assert!(max_db(&band_stop, 4500., 7500.) < -59.);
+        assert!(magnitude_db(&band_stop, 15000.).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_parks_mcclellan() {
+        for &len in &[101, 100] {
+            let bands = [
+                Band { start: 0., end: 4000., gain: 1., weight: 1. },
+                Band { start: 5000., end: 24000., gain: 0., weight: 10. },
+            ];
+            let kernel = parks_mcclellan(len, &bands, SAMPLE_RATE);
+            assert!(kernel.iter().zip(kernel.iter().rev()).all(|(a, b)| (a - b).abs() < 1e-7));
+            let deviation = |start: f64, end: f64, gain: f64| {
+                (0..=1000)
+                    .map(|i| start + (end - start) * i as f64 / 1000.)
+                    .map(|frequency| (response(&kernel, frequency, SAMPLE_RATE).norm() - gain).abs())
+                    .fold(0., f64::max)
+            };
+            let passband = deviation(0., 4000., 1.);
+            let stopband = deviation(5000., 24000., 0.);
+            // equiripple, with the errors in the ratio of the weights
+            assert!(passband < 0.05 && stopband < 0.005);
+            assert!((passband / stopband / 10. - 1.).abs() < 0.01, "{} {}", passband, stopband);
+        }
+    }
+
+    #[test]
+    fn test_minimum_phase() {
+        let linear = windowed_sinc(Passband::LowPass(4000.), 101, Window::Blackman, SAMPLE_RATE);
+        let minimum = minimum_phase(&linear);
+        for &frequency in &[0., 1000., 3000., 4000., 5000.] {
+            assert!((magnitude_db(&minimum, frequency) - magnitude_db(&linear, frequency)).abs() < 0.01);
+        }
+        assert!(max_db(&minimum, 6000., 24000.) < -70.);
+        // the energy moves from the middle of the kernel to its start
+        let energy = |kernel: &[f32]| kernel.iter().map(|x| x * x).sum::<f32>();
+        assert!(energy(&minimum[..25]) > 0.9 * energy(&minimum));
+    }
+
+    #[test]
+    fn test_kernels_filter_with_convolution() {
+        let tone = |frequency: f32| -> Vec<f32> {
+            (0..4800).map(|i| (2. * PI * frequency * i as f32 / SAMPLE_RATE as f32).sin()).collect()
+        };
+        let (low, high) = (tone(500.), tone(9000.));
+        let mixed: Vec<f32> = low.iter().zip(&high).map(|(a, b)| a + b).collect();
+        let kernel = windowed_sinc(Passband::LowPass(2000.), 255, Window::kaiser(80.), SAMPLE_RATE);
+
+        // same mode takes out the linear phase filter's delay, so the low tone comes out in place
+        let filtered = convolve(&mixed, &kernel, Mode::Same, &mut FftPlanner::new());
+        assert!(filtered[200..4600].iter().zip(&low[200..4600]).all(|(a, b)| (a - b).abs() < 1e-3));
+        let filtered = Convolver::new(&kernel).convolve(&mixed);
+        assert!(filtered[327..4727].iter().zip(&low[200..4600]).all(|(a, b)| (a - b).abs() < 1e-3));
+    }
+}
@@ -0,0 +1,593 @@
+use std::f64::consts::PI;
+
+use rustfft::num_complex::Complex;
+
+use crate::filters::BiquadCoefficients;
+
+/// The analog prototype that a filter is designed from. Butterworth and Bessel filters are 3 dB down at the
+/// cutoff frequency; Chebyshev I and elliptic filters have ripple of `ripple_db` decibels up to the cutoff
+/// frequency, which is the edge of the passband; and Chebyshev II filters are `attenuation_db` decibels down at
+/// the cutoff frequency, which is the edge of the stopband.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Prototype {
+    /// Maximally flat passband.
+    Butterworth,
+    /// Equiripple passband and a steeper rolloff than Butterworth.
+    ChebyshevI { ripple_db: f64 },
+    /// Flat passband and an equiripple stopband.
+    ChebyshevII { attenuation_db: f64 },
+    /// Equiripple passband and stopband, and the steepest transition of all for a given order.
+    Elliptic { ripple_db: f64, attenuation_db: f64 },
+    /// Maximally flat group delay, which keeps the shape of transients at the cost of a gentle rolloff.
+    Bessel,
+}
+
+/// Which frequencies a filter passes, in Hz.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Passband {
+    LowPass(f64),
+    HighPass(f64),
+    BandPass(f64, f64),
+    BandStop(f64, f64),
+}
+
+// a filter as its zeros, poles and gain
+struct Zpk {
+    zeros: Vec<Complex<f64>>,
+    poles: Vec<Complex<f64>>,
+    gain: f64,
+}
+
+impl Zpk {
+    fn degree(&self) -> usize {
+        self.poles.len() - self.zeros.len()
+    }
+}
+
+fn product(roots: &[Complex<f64>], f: impl Fn(Complex<f64>) -> Complex<f64>) -> Complex<f64> {
+    roots.iter().map(|&r| f(r)).fold(Complex::new(1., 0.), |a, b| a * b)
+}
+
+// prototypes are lowpass filters with a cutoff of 1 rad/s
+
+fn butterworth(order: usize) -> Zpk {
+    let n = order as f64;
+    let poles = (0..order)
+        .map(|k| Complex::from_polar(1., PI * (2. * k as f64 + n + 1.) / (2. * n)))
+        .collect();
+    Zpk {
+        zeros: Vec::new(),
+        poles,
+        gain: 1.,
+    }
+}
+
+fn chebyshev1(order: usize, ripple_db: f64) -> Zpk {
+    assert!(ripple_db > 0., "Passband ripple must be positive.");
+    let n = order as f64;
+    let epsilon = (10f64.powf(ripple_db / 10.) - 1.).sqrt();
+    let mu = (1. / epsilon).asinh() / n;
+    let poles: Vec<Complex<f64>> = (0..order)
+        .map(|k| {
+            let theta = PI * (2. * k as f64 + 1.) / (2. * n);
+            Complex::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos())
+        })
+        .collect();
+    // even orders start at the bottom of the ripple
+    let mut gain = product(&poles, |p| -p).re;
+    if order % 2 == 0 {
+        gain /= (1. + epsilon * epsilon).sqrt();
+    }
+    Zpk {
+        zeros: Vec::new(),
+        poles,
+        gain,
+    }
+}
+
+fn chebyshev2(order: usize, attenuation_db: f64) -> Zpk {
+    assert!(attenuation_db > 0., "Stopband attenuation must be positive.");
+    let n = order as f64;
+    let epsilon = 1. / (10f64.powf(attenuation_db / 10.) - 1.).sqrt();
+    let mu = (1. / epsilon).asinh() / n;
+    let mut zeros = Vec::new();
+    let mut poles = Vec::new();
+    for k in 0..order {
+        let theta = PI * (2. * k as f64 + 1.) / (2. * n);
+        poles.push(1. / Complex::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos()));
+        // odd orders have one zero at infinity
+        if 2 * k + 1 != order {
+            zeros.push(Complex::new(0., 1. / theta.cos()));
+        }
+    }
+    let gain = (product(&poles, |p| -p) / product(&zeros, |z| -z)).re;
+    Zpk { zeros, poles, gain }
+}
+
+// the Landen sequence of moduli, from which the elliptic functions below are computed as in Orfanidis, "Lecture
+// Notes on Elliptic Filter Design"
+fn landen(k: f64) -> Vec<f64> {
+    let mut moduli = Vec::new();
+    let mut k = k;
+    while moduli.len() < 32 {
+        k = (k / (1. + (1. - k * k).sqrt())).powi(2);
+        moduli.push(k);
+        if k < f64::EPSILON {
+            break;
+        }
+    }
+    moduli
+}
+
+// complete elliptic integral of the first kind
+fn ellipk(k: f64) -> f64 {
+    PI / 2. * landen(k).iter().map(|v| 1. + v).product::<f64>()
+}
+
+// the Jacobi elliptic function cd(uK, k), by descending Landen transformations of cos
+fn cde(u: Complex<f64>, k: f64) -> Complex<f64> {
+    landen(k)
+        .iter()
+        .rev()
+        .fold((u * PI / 2.).cos(), |w, &v| (1. + v) * w / (1. + v * w * w))
+}
+
+// the Jacobi elliptic function sn(uK, k)
+fn sne(u: Complex<f64>, k: f64) -> Complex<f64> {
+    landen(k)
+        .iter()
+        .rev()
+        .fold((u * PI / 2.).sin(), |w, &v| (1. + v) * w / (1. + v * w * w))
+}
+
+// symmetric remainder
+fn srem(x: f64, y: f64) -> f64 {
+    x - y * (x / y).round()
+}
+
+// the inverse of cde, in the fundamental period
+fn acde(w: Complex<f64>, k: f64) -> Complex<f64> {
+    let mut previous = k;
+    let mut w = w;
+    for v in landen(k) {
+        w = w / (1. + (1. - w * w * previous * previous).sqrt()) * 2. / (1. + v);
+        previous = v;
+    }
+    let u = w.acos() * 2. / PI;
+    let ratio = ellipk((1. - k * k).sqrt()) / ellipk(k);
+    Complex::new(srem(u.re, 4.), srem(u.im, 2. * ratio))
+}
+
+fn asne(w: Complex<f64>, k: f64) -> Complex<f64> {
+    1. - acde(w, k)
+}
+
+// the modulus k which, with the given order, meets the ripple specification given by k1
+fn ellipdeg(order: usize, k1: f64) -> f64 {
+    let n = order as f64;
+    let k1_complement = (1. - k1 * k1).sqrt();
+    let product: f64 = (1..=order / 2)
+        .map(|i| sne(Complex::new((2. * i as f64 - 1.) / n, 0.), k1_complement).re)
+        .product();
+    let k_complement = k1_complement.powi(order as i32) * product.powi(4);
+    (1. - k_complement * k_complement).sqrt()
+}
+
+fn elliptic(order: usize, ripple_db: f64, attenuation_db: f64) -> Zpk {
+    assert!(
+        0. < ripple_db && ripple_db < attenuation_db,
+        "Ripple must be positive and less than the stopband attenuation."
+    );
+    let n = order as f64;
+    let epsilon_pass = (10f64.powf(ripple_db / 10.) - 1.).sqrt();
+    let epsilon_stop = (10f64.powf(attenuation_db / 10.) - 1.).sqrt();
+    let k1 = epsilon_pass / epsilon_stop;
+    let k = ellipdeg(order, k1);
+
+    let i = Complex::new(0., 1.);
+    let v0 = -i * asne(i / epsilon_pass, k1) / n;
+    let mut zeros = Vec::new();
+    let mut poles = Vec::new();
+    for l in 1..=order / 2 {
+        let u = (2. * l as f64 - 1.) / n;
+        let zero = i / (k * cde(Complex::new(u, 0.), k));
+        let pole = i * cde(u - i * v0, k);
+        zeros.extend_from_slice(&[zero, zero.conj()]);
+        poles.extend_from_slice(&[pole, pole.conj()]);
+    }
+    if order % 2 == 1 {
+        poles.push(Complex::new((i * sne(i * v0, k)).re, 0.));
+    }
+
+    // even orders start at the bottom of the ripple
+    let dc_gain = if order % 2 == 0 { 1. / (1. + epsilon_pass * epsilon_pass).sqrt() } else { 1. };
+    let gain = dc_gain * (product(&poles, |p| -p) / product(&zeros, |z| -z)).re;
+    Zpk { zeros, poles, gain }
+}
+
+// roots of a polynomial with coefficients in increasing order of power, by the Durand-Kerner method
+fn roots(coefficients: &[f64]) -> Vec<Complex<f64>> {
+    let degree = coefficients.len() - 1;
+    let leading = coefficients[degree];
+    let evaluate = |x: Complex<f64>| {
+        coefficients.iter().rev().fold(Complex::new(0., 0.), |sum, &c| sum * x + c / leading)
+    };
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots: Vec<Complex<f64>> = (0..degree).map(|i| seed.powu(i as u32)).collect();
+    for _ in 0..1000 {
+        let mut change: f64 = 0.;
+        for i in 0..degree {
+            let denominator = (0..degree)
+                .filter(|&j| j != i)
+                .fold(Complex::new(1., 0.), |d, j| d * (roots[i] - roots[j]));
+            let step = evaluate(roots[i]) / denominator;
+            roots[i] -= step;
+            change = change.max(step.norm() / roots[i].norm().max(1.));
+        }
+        if change < 1e-15 {
+            break;
+        }
+    }
+    roots
+}
+
+fn bessel(order: usize) -> Zpk {
+    // the reverse Bessel polynomial, whose coefficients are (2n - k)! / (2^(n - k) k! (n - k)!)
+    let coefficients: Vec<f64> = (0..=order)
+        .map(|k| {
+            let numerator: f64 = (order - k + 1..=2 * order - k).map(|x| x as f64).product();
+            let denominator: f64 = (1..=k).map(|x| x as f64).product::<f64>() * 2f64.powi((order - k) as i32);
+            numerator / denominator
+        })
+        .collect();
+    let mut poles = roots(&coefficients);
+
+    // the polynomial gives unit delay at DC, so rescale to put the 3 dB point at the cutoff
+    let magnitude = |poles: &[Complex<f64>], w: f64| {
+        (product(poles, |p| -p) / product(poles, |p| Complex::new(0., w) - p)).norm()
+    };
+    let target = std::f64::consts::FRAC_1_SQRT_2;
+    let mut high = 1.;
+    while magnitude(&poles, high) > target {
+        high *= 2.;
+    }
+    let mut low = 0.;
+    for _ in 0..100 {
+        let middle = (low + high) / 2.;
+        if magnitude(&poles, middle) > target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let cutoff = (low + high) / 2.;
+    poles.iter_mut().for_each(|p| *p /= cutoff);
+    let gain = product(&poles, |p| -p).re;
+    Zpk {
+        zeros: Vec::new(),
+        poles,
+        gain,
+    }
+}
+
+fn lowpass_to_lowpass(zpk: Zpk, cutoff: f64) -> Zpk {
+    let degree = zpk.degree();
+    Zpk {
+        zeros: zpk.zeros.iter().map(|z| z * cutoff).collect(),
+        poles: zpk.poles.iter().map(|p| p * cutoff).collect(),
+        gain: zpk.gain * cutoff.powi(degree as i32),
+    }
+}
+
+fn lowpass_to_highpass(zpk: Zpk, cutoff: f64) -> Zpk {
+    let degree = zpk.degree();
+    let gain = zpk.gain * (product(&zpk.zeros, |z| -z) / product(&zpk.poles, |p| -p)).re;
+    let mut zeros: Vec<Complex<f64>> = zpk.zeros.iter().map(|z| cutoff / z).collect();
+    zeros.extend(std::iter::repeat(Complex::new(0., 0.)).take(degree));
+    Zpk {
+        zeros,
+        poles: zpk.poles.iter().map(|p| cutoff / p).collect(),
+        gain,
+    }
+}
+
+// each root r of the prototype maps to the two roots of s^2 - r bandwidth s + center^2
+fn split_roots(roots: &[Complex<f64>], center: f64) -> Vec<Complex<f64>> {
+    roots
+        .iter()
+        .flat_map(|&r| {
+            let offset = (r * r - center * center).sqrt();
+            vec![r + offset, r - offset]
+        })
+        .collect()
+}
+
+fn lowpass_to_bandpass(zpk: Zpk, center: f64, bandwidth: f64) -> Zpk {
+    let degree = zpk.degree();
+    let scale = |roots: &[Complex<f64>]| roots.iter().map(|r| r * bandwidth / 2.).collect::<Vec<_>>();
+    let mut zeros = split_roots(&scale(&zpk.zeros), center);
+    zeros.extend(std::iter::repeat(Complex::new(0., 0.)).take(degree));
+    Zpk {
+        zeros,
+        poles: split_roots(&scale(&zpk.poles), center),
+        gain: zpk.gain * bandwidth.powi(degree as i32),
+    }
+}
+
+fn lowpass_to_bandstop(zpk: Zpk, center: f64, bandwidth: f64) -> Zpk {
+    let degree = zpk.degree();
+    let gain = zpk.gain * (product(&zpk.zeros, |z| -z) / product(&zpk.poles, |p| -p)).re;
+    let invert = |roots: &[Complex<f64>]| roots.iter().map(|r| bandwidth / 2. / r).collect::<Vec<_>>();
+    let mut zeros = split_roots(&invert(&zpk.zeros), center);
+    for _ in 0..degree {
+        zeros.extend_from_slice(&[Complex::new(0., center), Complex::new(0., -center)]);
+    }
+    Zpk {
+        zeros,
+        poles: split_roots(&invert(&zpk.poles), center),
+        gain,
+    }
+}
+
+fn bilinear(zpk: Zpk, sample_rate: f64) -> Zpk {
+    let fs2 = 2. * sample_rate;
+    let degree = zpk.degree();
+    let gain = zpk.gain * (product(&zpk.zeros, |z| fs2 - z) / product(&zpk.poles, |p| fs2 - p)).re;
+    // zeros at infinity end up at the Nyquist frequency
+    let mut zeros: Vec<Complex<f64>> = zpk.zeros.iter().map(|z| (fs2 + z) / (fs2 - z)).collect();
+    zeros.extend(std::iter::repeat(Complex::new(-1., 0.)).take(degree));
+    Zpk {
+        zeros,
+        poles: zpk.poles.iter().map(|p| (fs2 + p) / (fs2 - p)).collect(),
+        gain,
+    }
+}
+
+fn is_real(r: &Complex<f64>) -> bool {
+    r.im.abs() <= 1e-10 * r.norm().max(1.)
+}
+
+// removes and returns the root in `roots` which is closest to `to`
+fn take_nearest<T: Copy>(roots: &mut Vec<T>, to: Complex<f64>, position: impl Fn(T) -> Complex<f64>) -> Option<T> {
+    let nearest = (0..roots.len()).min_by(|&a, &b| {
+        (position(roots[a]) - to).norm().partial_cmp(&(position(roots[b]) - to).norm()).unwrap()
+    })?;
+    Some(roots.swap_remove(nearest))
+}
+
+// splits roots into one of each complex conjugate pair, and the real roots
+fn split_conjugates(roots: &[Complex<f64>]) -> (Vec<Complex<f64>>, Vec<f64>) {
+    let complex = roots.iter().filter(|r| !is_real(r) && r.im > 0.).cloned().collect();
+    let real = roots.iter().filter(|r| is_real(r)).map(|r| r.re).collect();
+    (complex, real)
+}
+
+// the coefficients of the polynomial with the given roots, which are one or two real roots or a conjugate pair
+fn quadratic(roots: &[Complex<f64>]) -> (f64, f64) {
+    match roots {
+        [] => (0., 0.),
+        [r] => (-r.re, 0.),
+        [r1, r2] => (-(r1 + r2).re, (r1 * r2).re),
+        _ => unreachable!(),
+    }
+}
+
+// pairs each pole or pair of poles with the nearest zeros, starting from the poles nearest the unit circle, which
+// need their zeros the most to keep their sections' gains reasonable
+fn to_sections(zpk: Zpk) -> Vec<BiquadCoefficients> {
+    let (mut complex_zeros, mut real_zeros) = split_conjugates(&zpk.zeros);
+    let (complex_poles, mut real_poles) = split_conjugates(&zpk.poles);
+    real_poles.sort_by(|a, b| b.abs().partial_cmp(&a.abs()).unwrap());
+
+    let mut pole_groups: Vec<Vec<Complex<f64>>> = complex_poles.iter().map(|&p| vec![p, p.conj()]).collect();
+    // a leftover real pole goes first, so that it is sure to get the real zero left over with it
+    if real_poles.len() % 2 == 1 {
+        pole_groups.insert(0, vec![Complex::new(real_poles.pop().unwrap(), 0.)]);
+    }
+    for pair in real_poles.chunks(2) {
+        pole_groups.push(pair.iter().map(|&p| Complex::new(p, 0.)).collect());
+    }
+    let radius = |group: &[Complex<f64>]| group.iter().map(|p| p.norm()).fold(0., f64::max);
+    let lone = if pole_groups.first().is_some_and(|g| g.len() == 1) { 1 } else { 0 };
+    pole_groups[lone..].sort_by(|a, b| radius(b).partial_cmp(&radius(a)).unwrap());
+
+    let mut sections: Vec<(f64, BiquadCoefficients)> = pole_groups
+        .iter()
+        .map(|poles| {
+            let to = poles[0];
+            let real = |r: f64| Complex::new(r, 0.);
+            let zeros: Vec<Complex<f64>> = if poles.len() == 1 {
+                take_nearest(&mut real_zeros, to, real).map(real).into_iter().collect()
+            } else if (is_real(&to) || complex_zeros.is_empty()) && real_zeros.len() >= 2 {
+                let first = take_nearest(&mut real_zeros, to, real).unwrap();
+                let second = take_nearest(&mut real_zeros, poles[1], real).unwrap();
+                vec![real(first), real(second)]
+            } else if let Some(zero) = take_nearest(&mut complex_zeros, to, |z| z) {
+                vec![zero, zero.conj()]
+            } else {
+                real_zeros.drain(..real_zeros.len().min(2)).map(real).collect()
+            };
+            let (b1, b2) = quadratic(&zeros);
+            let (a1, a2) = quadratic(poles);
+            (radius(poles), BiquadCoefficients { b0: 1., b1, b2, a1, a2 })
+        })
+        .collect();
+
+    // the least resonant sections go first, and take the gain
+    sections.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut sections: Vec<BiquadCoefficients> = sections.into_iter().map(|(_, s)| s).collect();
+    if let Some(first) = sections.first_mut() {
+        first.b0 *= zpk.gain;
+        first.b1 *= zpk.gain;
+        first.b2 *= zpk.gain;
+    }
+    sections
+}
+
+/// Designs a digital IIR filter of the given prototype and order as second order sections, ready to run in a
+/// `BiquadCascade`. Band-pass and band-stop filters have twice `order` poles. The analog prototype is mapped to
+/// the band with the bilinear transform, with the band edges prewarped so that they land where they were asked
+/// for.
+pub fn design(prototype: Prototype, order: usize, band: Passband, sample_rate: u32) -> Vec<BiquadCoefficients> {
+    assert!(order > 0, "Filter order must be nonzero.");
+    let fs = sample_rate as f64;
+    let warp = |frequency: f64| {
+        assert!(
+            0. < frequency && frequency < fs / 2.,
+            "Filter frequencies must be between 0 and the Nyquist frequency."
+        );
+        2. * fs * (PI * frequency / fs).tan()
+    };
+
+    let zpk = match prototype {
+        Prototype::Butterworth => butterworth(order),
+        Prototype::ChebyshevI { ripple_db } => chebyshev1(order, ripple_db),
+        Prototype::ChebyshevII { attenuation_db } => chebyshev2(order, attenuation_db),
+        Prototype::Elliptic { ripple_db, attenuation_db } => elliptic(order, ripple_db, attenuation_db),
+        Prototype::Bessel => bessel(order),
+    };
+    let zpk = match band {
+        Passband::LowPass(cutoff) => lowpass_to_lowpass(zpk, warp(cutoff)),
+        Passband::HighPass(cutoff) => lowpass_to_highpass(zpk, warp(cutoff)),
+        Passband::BandPass(low, high) | Passband::BandStop(low, high) => {
+            assert!(low < high, "Band edges must be increasing.");
+            let (low, high) = (warp(low), warp(high));
+            if let Passband::BandPass(..) = band {
+                lowpass_to_bandpass(zpk, (low * high).sqrt(), high - low)
+            } else {
+                lowpass_to_bandstop(zpk, (low * high).sqrt(), high - low)
+            }
+        }
+    };
+    to_sections(bilinear(zpk, fs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::filters::BiquadCascade;
+
+    use super::{design, Passband, Prototype};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn magnitude(prototype: Prototype, order: usize, band: Passband, frequency: f64) -> f64 {
+        BiquadCascade::new(design(prototype, order, band, SAMPLE_RATE))
+            .response(frequency, SAMPLE_RATE)
+            .norm()
+    }
+
+    // the analog frequency that the bilinear transform maps `frequency` to, relative to `cutoff`'s
+    fn warped(frequency: f64, cutoff: f64) -> f64 {
+        let fs = SAMPLE_RATE as f64;
+        (PI * frequency / fs).tan() / (PI * cutoff / fs).tan()
+    }
+
+    fn chebyshev_polynomial(order: usize, x: f64) -> f64 {
+        if x.abs() <= 1. {
+            (order as f64 * x.acos()).cos()
+        } else {
+            (order as f64 * x.acosh()).cosh()
+        }
+    }
+
+    fn assert_near(computed: f64, expected: f64) {
+        assert!(
+            (computed - expected).abs() < 1e-6 * expected.max(1e-3),
+            "{} != {}",
+            computed,
+            expected
+        );
+    }
+
+    const FREQUENCIES: [f64; 8] = [10., 200., 700., 1000., 1300., 3000., 10000., 23000.];
+
+    #[test]
+    fn test_butterworth() {
+        for order in 1..=8 {
+            for &f in &FREQUENCIES {
+                let low = magnitude(Prototype::Butterworth, order, Passband::LowPass(1000.), f);
+                assert_near(low, 1. / (1. + warped(f, 1000.).powi(2 * order as i32)).sqrt());
+                let high = magnitude(Prototype::Butterworth, order, Passband::HighPass(1000.), f);
+                assert_near(high, 1. / (1. + warped(f, 1000.).powi(-2 * order as i32)).sqrt());
+            }
+        }
+    }
+
+    #[test]
+    fn test_chebyshev() {
+        let epsilon = (10f64.powf(0.1) - 1.).sqrt();
+        let prototype = Prototype::ChebyshevI { ripple_db: 1. };
+        for order in 1..=7 {
+            for &f in &FREQUENCIES {
+                let t = chebyshev_polynomial(order, warped(f, 1000.));
+                let expected = 1. / (1. + (epsilon * t).powi(2)).sqrt();
+                assert_near(magnitude(prototype, order, Passband::LowPass(1000.), f), expected);
+            }
+        }
+
+        let epsilon = 1. / (10f64.powf(6.) - 1.).sqrt();
+        let prototype = Prototype::ChebyshevII { attenuation_db: 60. };
+        for order in 1..=7 {
+            for &f in &FREQUENCIES {
+                let t = chebyshev_polynomial(order, 1. / warped(f, 1000.));
+                let expected = 1. / (1. + 1. / (epsilon * t).powi(2)).sqrt();
+                assert_near(magnitude(prototype, order, Passband::LowPass(1000.), f), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_elliptic() {
+        let prototype = Prototype::Elliptic { ripple_db: 0.5, attenuation_db: 60. };
+        let ripple = 10f64.powf(-0.5 / 20.);
+        let attenuation = 10f64.powf(-60. / 20.);
+        for order in 2..=7 {
+            let magnitude = |f: usize| magnitude(prototype, order, Passband::LowPass(1000.), f as f64);
+            // the passband ripples between 0 and -0.5 dB, reaching both, and ends at -0.5 dB
+            let passband: Vec<f64> = (0..1000).map(magnitude).collect();
+            assert!(passband.iter().all(|&m| m <= 1. + 1e-9 && m >= ripple - 1e-9));
+            assert!(passband.iter().any(|&m| m > 1. - 1e-6));
+            assert!((magnitude(1000) - ripple).abs() < 1e-9);
+            // once it reaches -60 dB, the stopband never rises above it again
+            let stopband_start = (1000..24000).find(|&f| magnitude(f) < attenuation).unwrap();
+            assert!((stopband_start..24000).step_by(7).all(|f| magnitude(f) <= attenuation + 1e-9));
+        }
+        // values from the analytic response, with the elliptic rational function computed separately
+        let expected = [
+            (500., -0.227_021),
+            (1050., -3.174_660),
+            (1300., -23.277_419),
+            (1500., -36.610_466),
+            (3000., -72.661_326),
+        ];
+        for &(f, expected_db) in &expected {
+            let db = 20. * magnitude(prototype, 5, Passband::LowPass(1000.), f).log10();
+            assert!((db - expected_db).abs() < 1e-3, "{}: {} != {}", f, db, expected_db);
+        }
+    }
+
+    #[test]
+    fn test_bessel() {
+        for order in 1..=8 {
+            let magnitude = |f| magnitude(Prototype::Bessel, order, Passband::LowPass(1000.), f);
+            assert_near(magnitude(1000.), std::f64::consts::FRAC_1_SQRT_2);
+            assert_near(magnitude(1.), 1.);
+        }
+    }
+
+    #[test]
+    fn test_band_pass_and_stop() {
+        let band_pass = |f| magnitude(Prototype::Butterworth, 4, Passband::BandPass(500., 2000.), f);
+        assert_near(band_pass(500.), std::f64::consts::FRAC_1_SQRT_2);
+        assert_near(band_pass(2000.), std::f64::consts::FRAC_1_SQRT_2);
+        assert!((band_pass(1000.) - 1.).abs() < 1e-3);
+        assert!(band_pass(50.) < 1e-3 && band_pass(20000.) < 1e-3);
+
+        let band_stop = |f| magnitude(Prototype::ChebyshevI { ripple_db: 1. }, 3, Passband::BandStop(500., 2000.), f);
+        assert!((band_stop(500.) - 10f64.powf(-1. / 20.)).abs() < 1e-6);
+        assert!(band_stop(1000.) < 1e-3);
+        assert!((band_stop(10.) - 1.).abs() < 1e-3);
+    }
+}
@@ -1,7 +1,13 @@
-mod reverb;
+pub mod reverb;
 mod raytracing;
-mod convolution;
-mod tuning;
-mod filters;
+pub mod convolution;
+pub mod tuning;
+pub mod filters;
+pub mod processor;
+mod resample;
 pub mod audio;
-pub mod noise;
\ No newline at end of file
+pub mod noise;
+pub mod sweep;
+pub mod iir;
+pub mod fir;
+pub mod oscillator;
\ No newline at end of file
@@ -2,7 +2,9 @@ pub mod audio;
 mod convolution;
 mod filters;
 pub mod noise;
+mod processor;
 mod raytracing;
+mod resample;
 mod reverb;
 mod tuning;
 
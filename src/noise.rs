@@ -1,28 +1,237 @@
+use std::f64::consts::PI;
+
 use rand::Rng;
 
-fn white_noise(n_samples: usize) -> Vec<f32> {
-    let mut rng = rand::thread_rng();
+// rows of the Voss-McCartney pink noise generator, which sets the lowest octave that it's pink down to, at the
+// sample rate over 2 to the power of the rows
+const PINK_ROWS: usize = 16;
+// how much the brown noise integrator leaks each sample, which keeps it from drifting off with a corner far below
+// the audible range at any usual sample rate
+const BROWN_LEAK: f64 = 0.9995;
+
+/// Uniformly distributed white noise between -1 and 1.
+pub fn white_noise<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f32> {
     (0..n_samples).map(|_| (rng.gen::<f32>() - 0.5) * 2.0).collect()
 }
 
+/// White noise with a normal distribution, with a mean of 0 and a standard deviation of 1, using the Box-Muller
+/// transform.
+pub fn gaussian_noise<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f32> {
+    (0..n_samples)
+        .map(|_| {
+            let radius = (-2. * (1. - rng.gen::<f64>()).ln()).sqrt();
+            (radius * (2. * PI * rng.gen::<f64>()).cos()) as f32
+        })
+        .collect()
+}
+
+fn normalize(samples: Vec<f64>) -> Vec<f32> {
+    let peak = samples.iter().fold(0., |a: f64, b| a.max(b.abs()));
+    let gain = if peak > 0. { 1. / peak } else { 0. };
+    samples.iter().map(|x| (x * gain) as f32).collect()
+}
+
+fn pink<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f64> {
+    let mut uniform = || rng.gen::<f64>() - 0.5;
+    let mut rows: Vec<f64> = (0..PINK_ROWS).map(|_| uniform()).collect();
+    let mut total: f64 = rows.iter().sum();
+    (0..n_samples)
+        .map(|i| {
+            // row k is redrawn every 2^(k + 1) samples, staggered so that only one row changes at a time
+            let k = (i + 1).trailing_zeros() as usize;
+            if k < PINK_ROWS {
+                let row = uniform();
+                total += row - rows[k];
+                rows[k] = row;
+            }
+            total + uniform()
+        })
+        .collect()
+}
+
+fn differentiate(samples: &[f64]) -> Vec<f64> {
+    std::iter::once(0.).chain(samples.iter().cloned()).zip(samples).map(|(previous, x)| x - previous).collect()
+}
+
+/// Pink noise, with equal power in every octave, falling by 3 dB per octave, from the Voss-McCartney algorithm.
+/// It's scaled to a peak of 1, as are the other colors.
+pub fn pink_noise<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f32> {
+    normalize(pink(n_samples, rng))
+}
+
+/// Brown, or red, noise, falling by 6 dB per octave: a random walk, which leaks back towards zero so that it
+/// doesn't wander off.
+pub fn brown_noise<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f32> {
+    let mut total = 0.;
+    let walk = white_noise(n_samples, rng)
+        .iter()
+        .map(|&x| {
+            total = BROWN_LEAK * total + x as f64;
+            total
+        })
+        .collect();
+    normalize(walk)
+}
+
+/// Blue noise, rising by 3 dB per octave: differentiated pink noise.
+pub fn blue_noise<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f32> {
+    normalize(differentiate(&pink(n_samples, rng)))
+}
+
+/// Violet noise, rising by 6 dB per octave: differentiated white noise.
+pub fn violet_noise<R: Rng + ?Sized>(n_samples: usize, rng: &mut R) -> Vec<f32> {
+    let white: Vec<f64> = white_noise(n_samples, rng).iter().map(|&x| x as f64).collect();
+    normalize(differentiate(&white))
+}
+
+/// Velvet noise: impulses of 1 or -1 at `density` impulses per second, one at a random position in each
+/// interval of the sample rate over the density, and zeros elsewhere. Its spectrum is white, but being mostly
+/// zeros, it's cheap to convolve with, and it sounds smoother than white noise at densities of a couple of
+/// thousand impulses per second.
+pub fn velvet_noise<R: Rng + ?Sized>(n_samples: usize, density: f32, sample_rate: u32, rng: &mut R) -> Vec<f32> {
+    assert!(
+        0. < density && density <= sample_rate as f32,
+        "Velvet noise density must be positive and at most the sample rate."
+    );
+    let interval = sample_rate as f64 / density as f64;
+    let mut noise = vec![0.; n_samples];
+    for m in 0.. {
+        let position = (m as f64 * interval + rng.gen::<f64>() * (interval - 1.)).round() as usize;
+        if position >= n_samples {
+            break;
+        }
+        noise[position] = if rng.gen::<bool>() { 1. } else { -1. };
+    }
+    noise
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs::File, path::Path};
 
     use crate::audio::Audio;
 
-    use super::white_noise;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    use super::{blue_noise, brown_noise, gaussian_noise, pink_noise, velvet_noise, violet_noise, white_noise};
 
     #[test]
     fn test_noise() {
         let mut file = File::open(Path::new("data/3.wav")).unwrap();
         let mut out_file = File::create("data/3_test.wav").unwrap();
         let mut audio = Audio::from_wav(&mut file).unwrap();
-        let noise = white_noise(audio.samples[0].len());
+        let noise = white_noise(audio.samples[0].len(), &mut rng());
         for i in 0..noise.len() {
             audio.samples[0][i] = audio.samples[0][i] + noise[i] * 0.003;
             audio.samples[1][i] = audio.samples[1][i] + noise[i] * 0.003;
         }
         audio.to_wav(&mut out_file).unwrap();
     }
-}
\ No newline at end of file
+
+    // the slope of the noise's power spectral density in dB per octave, from the mean density in the octaves
+    // between 1/512 and 1/8 of the sample rate, averaged over Hann windowed segments
+    fn spectral_slope(noise: &[f32]) -> f64 {
+        const SEGMENT: usize = 4096;
+        let fft = FftPlanner::new().plan_fft_forward(SEGMENT);
+        let mut power = vec![0f64; SEGMENT / 2];
+        for segment in noise.chunks_exact(SEGMENT) {
+            let mut buffer: Vec<Complex<f64>> = segment
+                .iter()
+                .enumerate()
+                .map(|(i, &x)| {
+                    let window = 0.5 - 0.5 * (2. * std::f64::consts::PI * i as f64 / SEGMENT as f64).cos();
+                    Complex::new(x as f64 * window, 0.)
+                })
+                .collect();
+            fft.process(&mut buffer);
+            power.iter_mut().zip(&buffer).for_each(|(p, x)| *p += x.norm_sqr());
+        }
+        let octaves: Vec<(f64, f64)> = (0..6)
+            .map(|octave| {
+                let start = (SEGMENT / 512) << octave;
+                let bins = &power[start..2 * start];
+                (octave as f64, 10. * (bins.iter().sum::<f64>() / bins.len() as f64).log10())
+            })
+            .collect();
+        // least squares fit of the level against the octave
+        let n = octaves.len() as f64;
+        let mean_x = octaves.iter().map(|o| o.0).sum::<f64>() / n;
+        let mean_y = octaves.iter().map(|o| o.1).sum::<f64>() / n;
+        let covariance: f64 = octaves.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = octaves.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        covariance / variance
+    }
+
+    const N_SAMPLES: usize = 1 << 18;
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(1)
+    }
+
+    fn assert_slope(noise: &[f32], expected: f64) {
+        let slope = spectral_slope(noise);
+        assert!((slope - expected).abs() < 0.5, "{} dB/octave, expected {}", slope, expected);
+    }
+
+    #[test]
+    fn test_white_noise() {
+        let noise = white_noise(N_SAMPLES, &mut rng());
+        assert!(noise.iter().all(|x| x.abs() <= 1.));
+        assert_slope(&noise, 0.);
+    }
+
+    #[test]
+    fn test_gaussian_noise() {
+        let noise = gaussian_noise(N_SAMPLES, &mut rng());
+        let mean = noise.iter().map(|&x| x as f64).sum::<f64>() / N_SAMPLES as f64;
+        let variance = noise.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / N_SAMPLES as f64;
+        assert!(mean.abs() < 0.01 && (variance - 1.).abs() < 0.01);
+        // about 4.6% of normally distributed values are more than two standard deviations out
+        let outliers = noise.iter().filter(|x| x.abs() > 2.).count() as f64 / N_SAMPLES as f64;
+        assert!((outliers - 0.0455).abs() < 0.002);
+        assert_slope(&noise, 0.);
+    }
+
+    #[test]
+    fn test_pink_noise() {
+        assert_slope(&pink_noise(N_SAMPLES, &mut rng()), -3.);
+    }
+
+    #[test]
+    fn test_brown_noise() {
+        assert_slope(&brown_noise(N_SAMPLES, &mut rng()), -6.);
+    }
+
+    #[test]
+    fn test_blue_noise() {
+        assert_slope(&blue_noise(N_SAMPLES, &mut rng()), 3.);
+    }
+
+    #[test]
+    fn test_violet_noise() {
+        assert_slope(&violet_noise(N_SAMPLES, &mut rng()), 6.);
+    }
+
+    #[test]
+    fn test_velvet_noise() {
+        // one impulse in each 24 sample interval
+        let noise = velvet_noise(24 * 20000, 2000., 48000, &mut rng());
+        assert_eq!(noise.iter().filter(|&&x| x != 0.).count(), 20000);
+        assert!(noise.iter().all(|&x| x == 0. || x.abs() == 1.));
+        assert_slope(&noise, 0.);
+    }
+
+    #[test]
+    fn test_seeded_noise_is_reproducible() {
+        let seeded = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut noise = white_noise(100, &mut rng);
+            noise.extend(pink_noise(100, &mut rng));
+            noise.extend(velvet_noise(100, 4800., 48000, &mut rng));
+            noise
+        };
+        assert_eq!(seeded(7), seeded(7));
+        assert_ne!(seeded(7), seeded(8));
+    }
+}
@@ -0,0 +1,195 @@
+use std::f64::consts::PI;
+
+use crate::tuning::IntervalTuningSystem;
+
+/// The shape of an oscillator's wave, each swinging between -1 and 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    /// Rises over each period and drops at the start of the next.
+    Saw,
+    Square,
+    Triangle,
+}
+
+// the difference between a band-limited step and a naive one, for a step of 2 at phase 0, smoothed over the
+// samples either side of it with a polynomial (Välimäki's PolyBLEP)
+fn polyblep(phase: f64, increment: f64) -> f64 {
+    if phase < increment {
+        let x = phase / increment;
+        2. * x - x * x - 1.
+    } else if phase > 1. - increment {
+        let x = (phase - 1.) / increment;
+        x * x + 2. * x + 1.
+    } else {
+        0.
+    }
+}
+
+// the integral of half of `polyblep`, which band-limits a corner where the slope rises by 1 per sample
+fn polyblamp(phase: f64, increment: f64) -> f64 {
+    if phase < increment {
+        (1. - phase / increment).powi(3) / 6.
+    } else if phase > 1. - increment {
+        ((phase - 1.) / increment + 1.).powi(3) / 6.
+    } else {
+        0.
+    }
+}
+
+/// A band-limited oscillator. The discontinuities of the saw and square waves, and the corners of the triangle,
+/// are smoothed with polynomial band-limited steps and ramps, which keeps aliasing low without the memory of a
+/// wavetable. Changing the frequency keeps the phase, so the wave carries on without a click.
+#[derive(Debug, Clone)]
+pub struct Oscillator {
+    waveform: Waveform,
+    frequency: f64,
+    sample_rate: u32,
+    // the position in the current period, from 0 to 1
+    phase: f64,
+}
+
+impl Oscillator {
+    pub fn new(waveform: Waveform, frequency: f64, sample_rate: u32) -> Oscillator {
+        assert!(sample_rate > 0, "Sample rate must be nonzero.");
+        let mut oscillator = Oscillator {
+            waveform,
+            frequency: 0.,
+            sample_rate,
+            phase: 0.,
+        };
+        oscillator.set_frequency(frequency);
+        oscillator
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Changes the frequency from the next sample on, carrying on from the current phase.
+    pub fn set_frequency(&mut self, frequency: f64) {
+        assert!(
+            0. <= frequency && frequency < self.sample_rate as f64 / 2.,
+            "Oscillator frequency must be between 0 and the Nyquist frequency."
+        );
+        self.frequency = frequency;
+    }
+
+    /// Tunes the oscillator to the note at `position` in `octave` of a tuning system.
+    pub fn set_note<T: IntervalTuningSystem + ?Sized>(&mut self, tuning: &T, position: f64, octave: i32) {
+        self.set_frequency(tuning.freq(position, octave));
+    }
+
+    /// The position in the current period, from 0 to 1.
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    pub fn set_phase(&mut self, phase: f64) {
+        self.phase = phase.rem_euclid(1.);
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let phase = self.phase;
+        let increment = self.frequency / self.sample_rate as f64;
+        let value = match self.waveform {
+            Waveform::Sine => (2. * PI * phase).sin(),
+            Waveform::Saw => 2. * phase - 1. - polyblep(phase, increment),
+            Waveform::Square => {
+                let naive = if phase < 0.5 { 1. } else { -1. };
+                naive + polyblep(phase, increment) - polyblep((phase + 0.5) % 1., increment)
+            }
+            Waveform::Triangle => {
+                // the slope changes by 8 per period at each corner
+                let naive = 1. - 4. * (phase - 0.5).abs();
+                let corner = 8. * increment;
+                naive + corner * (polyblamp(phase, increment) - polyblamp((phase + 0.5) % 1., increment))
+            }
+        };
+        self.phase = (phase + increment) % 1.;
+        value as f32
+    }
+
+    /// Fills `samples` with the next samples of the wave.
+    pub fn fill(&mut self, samples: &mut [f32]) {
+        samples.iter_mut().for_each(|s| *s = self.next_sample());
+    }
+
+    pub fn generate(&mut self, n_samples: usize) -> Vec<f32> {
+        (0..n_samples).map(|_| self.next_sample()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    use crate::tuning::a440;
+
+    use super::{Oscillator, Waveform};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    // the power of the partials that aren't harmonics of `bin`, relative to those that are, in dB, from a spectrum
+    // in which every partial falls on a bin
+    fn aliasing_db(samples: &[f32], bin: usize) -> f64 {
+        let mut spectrum: Vec<Complex<f64>> = samples.iter().map(|&x| Complex::new(x as f64, 0.)).collect();
+        FftPlanner::new().plan_fft_forward(spectrum.len()).process(&mut spectrum);
+        let (mut harmonics, mut aliases) = (0., 0.);
+        for (i, x) in spectrum[1..spectrum.len() / 2].iter().enumerate() {
+            if (i + 1) % bin == 0 {
+                harmonics += x.norm_sqr();
+            } else {
+                aliases += x.norm_sqr();
+            }
+        }
+        10. * (aliases / harmonics).log10()
+    }
+
+    #[test]
+    fn test_waveforms_are_band_limited() {
+        // a frequency that falls on bin 93 of the FFT, so that its aliases fall between the harmonics' bins
+        let len = 4096;
+        let frequency = 93. * SAMPLE_RATE as f64 / len as f64;
+        // a naive saw aliases at about -16 dB here
+        let limits = [
+            (Waveform::Sine, -100.),
+            (Waveform::Saw, -30.),
+            (Waveform::Square, -30.),
+            (Waveform::Triangle, -55.),
+        ];
+        for &(waveform, limit) in &limits {
+            let samples = Oscillator::new(waveform, frequency, SAMPLE_RATE).generate(len);
+            assert!(samples.iter().all(|x| x.abs() < 1.1));
+            let band_limited = aliasing_db(&samples, 93);
+            assert!(band_limited < limit, "{:?}: {} dB", waveform, band_limited);
+        }
+    }
+
+    #[test]
+    fn test_frequency_changes_are_continuous() {
+        let mut oscillator = Oscillator::new(Waveform::Sine, 440., SAMPLE_RATE);
+        let mut samples = oscillator.generate(1000);
+        oscillator.set_frequency(880.);
+        samples.extend(oscillator.generate(1000));
+        let max_step = 2. * std::f32::consts::PI * 880. / SAMPLE_RATE as f32;
+        assert!(samples.windows(2).all(|w| (w[1] - w[0]).abs() <= max_step * 1.001));
+    }
+
+    #[test]
+    fn test_set_note() {
+        let mut oscillator = Oscillator::new(Waveform::Saw, 100., SAMPLE_RATE);
+        oscillator.set_note(&a440(), 9., 4);
+        assert!((oscillator.frequency() - 440.).abs() < 1e-9);
+        oscillator.set_note(&a440(), 0., 4);
+        assert!((oscillator.frequency() - 261.6256).abs() < 1e-4);
+    }
+}
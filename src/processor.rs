@@ -0,0 +1,130 @@
+use rand::{rngs::StdRng, SeedableRng};
+use rustfft::FftPlanner;
+
+use crate::convolution::gcc_phat;
+use crate::noise::white_noise;
+
+/// An effect which processes audio a block at a time, for use in real-time callbacks.
+///
+/// `prepare` is where a processor allocates; `process` should not allocate except to grow its per-channel
+/// state the first time it sees more channels than before.
+pub trait Processor {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize);
+
+    /// Processes one block in place; each slice is a channel, and all channels have the same length, which is
+    /// at most the `max_block` given to `prepare`.
+    fn process(&mut self, channels: &mut [&mut [f32]]);
+
+    /// Clears any internal state, as if no audio had been processed since `prepare`.
+    fn reset(&mut self);
+
+    /// The number of samples by which the processor delays its input.
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+/// Runs processors one after another on the same block.
+#[derive(Default)]
+pub struct Chain {
+    processors: Vec<Box<dyn Processor>>,
+}
+
+impl Chain {
+    pub fn new() -> Chain {
+        Chain::default()
+    }
+
+    pub fn push<P: Processor + 'static>(&mut self, processor: P) {
+        self.processors.push(Box::new(processor));
+    }
+}
+
+impl Processor for Chain {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.processors
+            .iter_mut()
+            .for_each(|p| p.prepare(sample_rate, max_block));
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        self.processors.iter_mut().for_each(|p| p.process(channels));
+    }
+
+    fn reset(&mut self) {
+        self.processors.iter_mut().for_each(|p| p.reset());
+    }
+
+    fn latency(&self) -> usize {
+        self.processors.iter().map(|p| p.latency()).sum()
+    }
+}
+
+/// Runs `processor` over whole channels in blocks of `block_size`, the way a real-time host would.
+pub fn process_offline<P: Processor + ?Sized>(
+    processor: &mut P,
+    samples: &mut [Vec<f32>],
+    sample_rate: u32,
+    block_size: usize,
+) {
+    processor.prepare(sample_rate, block_size);
+    let n_samples = samples.first().map_or(0, |s| s.len());
+    for start in (0..n_samples).step_by(block_size) {
+        let end = n_samples.min(start + block_size);
+        let mut block: Vec<&mut [f32]> = samples.iter_mut().map(|s| &mut s[start..end]).collect();
+        processor.process(&mut block);
+    }
+}
+
+/// Measures how many samples `processor` delays its input by running a second of noise through it and locating
+/// the delay with GCC-PHAT, to check the `latency` that it reports. The noise is the same every time, so the
+/// measurement is repeatable.
+pub fn measure_latency<P: Processor + ?Sized>(processor: &mut P, sample_rate: u32, block_size: usize) -> f32 {
+    let len = sample_rate as usize;
+    let noise = white_noise(len, &mut StdRng::seed_from_u64(0));
+    let mut samples = vec![noise.clone()];
+    process_offline(processor, &mut samples, sample_rate, block_size);
+    gcc_phat(&samples[0], &noise, len / 2, &mut FftPlanner::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::PartitionedConvolver;
+
+    use super::{measure_latency, process_offline, Chain, Processor};
+
+    struct Gain(f32);
+
+    impl Processor for Gain {
+        fn prepare(&mut self, _sample_rate: u32, _max_block: usize) {}
+
+        fn process(&mut self, channels: &mut [&mut [f32]]) {
+            channels
+                .iter_mut()
+                .for_each(|c| c.iter_mut().for_each(|s| *s *= self.0));
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn test_chain() {
+        let mut chain = Chain::new();
+        chain.push(Gain(2.));
+        chain.push(Gain(0.25));
+        let mut samples = vec![vec![1., 2., 3.], vec![4., 5., 6.]];
+        process_offline(&mut chain, &mut samples, 44100, 2);
+        assert_eq!(samples, vec![vec![0.5, 1., 1.5], vec![2., 2.5, 3.]]);
+    }
+
+    #[test]
+    fn test_measure_latency() {
+        let mut chain = Chain::new();
+        chain.push(Gain(0.5));
+        chain.push(PartitionedConvolver::new(&[0., 0., 1.], 64, &mut FftPlanner::new()));
+        let measured = measure_latency(&mut chain, 8000, 100);
+        assert!((measured - (chain.latency() + 2) as f32).abs() < 0.5, "measured {}", measured);
+    }
+}
@@ -3,7 +3,7 @@ use parry3d::{
     na::{Point3, Vector3},
     query::{Ray, RayCast},
 };
-use rand::{rngs::ThreadRng, Rng};
+use rand::Rng;
 use std::f64::consts::PI;
 
 fn proj(u: &Vector3<Real>, v: &Vector3<Real>) -> Vector3<Real> {
@@ -55,7 +55,7 @@ pub fn forward_ray_trace(
     }
 }
 
-pub fn random_spherical_direction(rng: &mut ThreadRng) -> Vector3<f32> {
+pub fn random_spherical_direction<R: Rng + ?Sized>(rng: &mut R) -> Vector3<f32> {
     let u: f32 = rng.gen_range(-1.0..1.0);
     let t: f32 = rng.gen_range(0.0..PI as f32);
 
@@ -66,23 +66,43 @@ pub fn random_spherical_direction(rng: &mut ThreadRng) -> Vector3<f32> {
     Vector3::new(x, y, z)
 }
 
-pub fn profile_room(
-    room: &Vec<&dyn RayCast>,
+/// How `profile_room` traces a room and turns the paths it finds into an impulse response.
+#[derive(Debug, Copy, Clone)]
+pub struct TracingParameters {
+    /// How many rays to trace.
+    pub samples: usize,
+    pub max_bounces: usize,
+    /// The longest path to follow, in seconds.
+    pub max_delay: f32,
+    pub speed_of_sound: f32,
+    /// The fraction of pressure lost at each bounce.
+    pub decay: f32,
+    /// The pressure of a path one unit long.
+    pub base_impulse: f32,
+    pub sample_rate: f32,
+}
+
+/// Traces rays in random directions from `speaker`, drawn from `rng`, and gathers the ones that reach
+/// `microphone` into an impulse response.
+pub fn profile_room<R: Rng + ?Sized>(
+    room: &[&dyn RayCast],
     speaker: &Point3<f32>,
     microphone: &dyn RayCast,
-    samples: usize,
-    max_bounces: usize,
-    max_delay: f32,
-    speed_of_sound: f32,
-    decay: f32,
-    base_impulse: f32,
-    sample_rate: f32,
+    parameters: &TracingParameters,
+    rng: &mut R,
 ) -> Vec<f32> {
-    let mut rng = rand::thread_rng();
-
+    let TracingParameters {
+        samples,
+        max_bounces,
+        max_delay,
+        speed_of_sound,
+        decay,
+        base_impulse,
+        sample_rate,
+    } = *parameters;
     let max_distance = max_delay * speed_of_sound;
 
-    let mut geometry = room.clone();
+    let mut geometry = room.to_vec();
     geometry.push(microphone);
 
     let mut microphone_hits: Vec<(f32, usize)> = Vec::new();
@@ -93,7 +113,7 @@ pub fn profile_room(
             println!("{}/{}", s, samples);
         }
 
-        let dir = random_spherical_direction(&mut rng);
+        let dir = random_spherical_direction(rng);
         let r = Ray::new(*speaker, dir);
 
         let hits = forward_ray_trace(&r, &geometry, 0, max_bounces, 0., max_distance);
@@ -137,8 +157,9 @@ mod tests {
         na::Vector3,
         query::{Ray, RayCast},
     };
+    use rand::{rngs::StdRng, SeedableRng};
 
-    use crate::raytracing::forward_ray_trace;
+    use crate::raytracing::{forward_ray_trace, profile_room, TracingParameters};
 
     #[test]
     fn test_forward_ray_trace() {
@@ -159,4 +180,27 @@ mod tests {
         assert!(hits.len() == 50);
         assert!(microphone_hits == 9);
     }
+
+    #[test]
+    fn test_profile_room_is_seeded() {
+        let room = Aabb::new(Point::new(0.0, 0.0, 0.0), Point::new(10.0, 10.0, 10.0));
+        let microphone = Aabb::new(Point::new(4.0, 4.0, 8.0), Point::new(6.0, 6.0, 9.0));
+        let geometry: Vec<&dyn RayCast> = vec![&room];
+        let parameters = TracingParameters {
+            samples: 200,
+            max_bounces: 20,
+            max_delay: 1.,
+            speed_of_sound: 343.,
+            decay: 0.1,
+            base_impulse: 1.,
+            sample_rate: 8000.,
+        };
+        let profile = |seed: u64| {
+            let speaker = Point::new(5.0, 5.0, 2.0);
+            let mut rng = StdRng::seed_from_u64(seed);
+            profile_room(&geometry, &speaker, &microphone, &parameters, &mut rng)
+        };
+        assert_eq!(profile(5), profile(5));
+        assert_ne!(profile(5), profile(6));
+    }
 }
@@ -0,0 +1,150 @@
+use std::f64::consts::PI;
+
+const HALF_TAPS: usize = 32;
+const PHASES: usize = 256;
+const KAISER_BETA: f64 = 8.6;
+// leave a little room below nyquist for the filter's transition band
+const ROLLOFF: f64 = 0.95;
+
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+pub(crate) fn kaiser(x: f64, beta: f64) -> f64 {
+    if x.abs() > 1.0 {
+        0.0
+    } else {
+        bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+    }
+}
+
+pub(crate) fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Band-limited sample rate converter for arbitrary integer rates, using a Kaiser windowed sinc filter stored
+/// as a table of polyphase branches which are linearly interpolated between.
+pub struct Resampler {
+    from_rate: u64,
+    to_rate: u64,
+    half_taps: usize,
+    table: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Resampler {
+        assert!(from_rate > 0 && to_rate > 0, "Sample rates must be nonzero.");
+        // when downsampling, the cutoff has to drop to the new nyquist frequency to avoid aliasing, and the filter
+        // has to stretch by the same ratio so that its transition band narrows along with it
+        let ratio = (from_rate as f64 / to_rate as f64).max(1.0);
+        let cutoff = ROLLOFF / ratio;
+        let half_taps = (HALF_TAPS as f64 * ratio).ceil() as usize;
+        let n_taps = 2 * half_taps;
+        let mut table = Vec::with_capacity((PHASES + 1) * n_taps);
+        for phase in 0..=PHASES {
+            let offset = phase as f64 / PHASES as f64 + (half_taps - 1) as f64;
+            for k in 0..n_taps {
+                let x = offset - k as f64;
+                table.push((cutoff * sinc(cutoff * x) * kaiser(x / half_taps as f64, KAISER_BETA)) as f32);
+            }
+        }
+        Resampler {
+            from_rate: from_rate as u64,
+            to_rate: to_rate as u64,
+            half_taps,
+            table,
+        }
+    }
+
+    pub fn output_len(&self, input_len: usize) -> usize {
+        (input_len as u64 * self.to_rate).div_ceil(self.from_rate) as usize
+    }
+
+    pub fn process(&self, samples: &[f32]) -> Vec<f32> {
+        let n_taps = 2 * self.half_taps;
+        (0..self.output_len(samples.len()) as u64)
+            .map(|n| {
+                // keep the input position as an exact fraction so that long signals don't drift
+                let numerator = n * self.from_rate;
+                let i = (numerator / self.to_rate) as i64;
+                let fraction = (numerator % self.to_rate) as f64 / self.to_rate as f64 * PHASES as f64;
+                let phase = fraction.floor() as usize;
+                let weight = (fraction - phase as f64) as f32;
+                let lower = &self.table[phase * n_taps..(phase + 1) * n_taps];
+                let upper = &self.table[(phase + 1) * n_taps..(phase + 2) * n_taps];
+
+                let start = i - self.half_taps as i64 + 1;
+                (0..n_taps)
+                    .filter_map(|k| {
+                        let j = start + k as i64;
+                        if j < 0 || j >= samples.len() as i64 {
+                            None
+                        } else {
+                            let h = lower[k] + (upper[k] - lower[k]) * weight;
+                            Some(samples[j as usize] * h)
+                        }
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate {
+        return samples.to_vec();
+    }
+    Resampler::new(from_rate, to_rate).process(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::resample;
+
+    fn sine(freq: f32, rate: u32, n: usize) -> Vec<f32> {
+        (0..n).map(|i| (2. * PI * freq * i as f32 / rate as f32).sin()).collect()
+    }
+
+    #[test]
+    fn test_resample_sine() {
+        let input = sine(1000., 44100, 4410);
+        let output = resample(&input, 44100, 48000);
+        assert_eq!(output.len(), 4800);
+        let expected = sine(1000., 48000, 4800);
+        // ignore the edges, where the filter runs off the end of the signal
+        let max_error = output[100..4700]
+            .iter()
+            .zip(&expected[100..4700])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f32::max);
+        assert!(max_error < 1e-3, "max error {}", max_error);
+    }
+
+    #[test]
+    fn test_downsample_removes_aliases() {
+        for &(freq, from_rate, to_rate) in &[(15000., 48000, 22050), (5000., 96000, 8000), (6000., 192000, 8000)] {
+            let input = sine(freq, from_rate, from_rate as usize / 10);
+            let output = resample(&input, from_rate, to_rate);
+            // ignore the edges, where the filter runs off the end of the signal
+            let edge = output.len() / 4;
+            let rms = (output[edge..output.len() - edge].iter().map(|s| s * s).sum::<f32>()
+                / (output.len() - 2 * edge) as f32)
+                .sqrt();
+            assert!(rms < 1e-3, "{} to {}: rms {}", from_rate, to_rate, rms);
+        }
+    }
+}
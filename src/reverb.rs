@@ -1,60 +1,132 @@
 use parry3d::bounding_volume::Aabb;
 use parry3d::math::Point;
 use parry3d::query::RayCast;
+use rand::{rngs::StdRng, SeedableRng};
 use rustfft::FftPlanner;
 use std::time::Instant;
 use std::{fs::File, path::Path};
 
-use crate::audio::Audio;
-use crate::convolution::rfft_convolve;
-use crate::raytracing::profile_room;
+use crate::audio::{Audio, ChannelLayout};
+use crate::convolution::{MatrixConvolver, NonUniformConvolver};
+use crate::processor::Processor;
+use crate::raytracing::{profile_room, TracingParameters};
+use crate::resample::resample;
+
+const IMPULSE_RESPONSE_SAMPLE_RATE: u32 = 44100;
+
+/// Convolution reverb which mixes the input with its convolution by a room's impulse response, with no latency
+/// even for impulse responses several seconds long.
+pub struct Reverb {
+    convolver: NonUniformConvolver,
+    mix: f32,
+    max_block: usize,
+    dry: Vec<Vec<f32>>,
+}
+
+impl Reverb {
+    /// `mix` is the proportion of the output which is reverberated, from 0 (dry) to 1 (wet).
+    pub fn new(impulse_response: Vec<f32>, mix: f32) -> Reverb {
+        Reverb {
+            convolver: NonUniformConvolver::new(&impulse_response, 64, 8192, &mut FftPlanner::new()),
+            mix,
+            max_block: 0,
+            dry: Vec::new(),
+        }
+    }
+}
+
+impl Processor for Reverb {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize) {
+        self.convolver.prepare(sample_rate, max_block);
+        self.max_block = max_block;
+        self.dry.iter_mut().for_each(|d| d.resize(max_block, 0.));
+    }
+
+    fn process(&mut self, channels: &mut [&mut [f32]]) {
+        assert!(self.max_block > 0, "Reverb::prepare must be called before process.");
+        if self.dry.len() < channels.len() {
+            self.dry.resize(channels.len(), vec![0.; self.max_block]);
+        }
+        for (channel, dry) in channels.iter().zip(self.dry.iter_mut()) {
+            dry[..channel.len()].copy_from_slice(channel);
+        }
+        self.convolver.process(channels);
+        for (channel, dry) in channels.iter_mut().zip(&self.dry) {
+            for (wet, &dry) in channel.iter_mut().zip(dry) {
+                *wet = dry * (1. - self.mix) + *wet * self.mix;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.convolver.reset();
+    }
+}
 
 pub fn demo(path: &Path) {
     let room = Aabb::new(Point::new(0.0, 0.0, 0.0), Point::new(10.0, 10.0, 100.0));
-    let speaker = Point::new(5.0, 5.0, 1.0);
-    let microphone = Aabb::new(Point::new(4.9, 4.9, 99.0), Point::new(5.1, 5.1, 99.1));
+    let microphones = [
+        Aabb::new(Point::new(4.4, 4.9, 99.0), Point::new(4.6, 5.1, 99.1)),
+        Aabb::new(Point::new(5.4, 4.9, 99.0), Point::new(5.6, 5.1, 99.1)),
+    ];
 
     let mut geometry: Vec<&dyn RayCast> = Vec::new();
     geometry.push(&room);
 
-    let t = Instant::now();
-    let kernel = profile_room(
-        &geometry,
-        &speaker,
-        &microphone,
-        1000,
-        1000,
-        100.,
-        343.,
-        0.01,
-        1000.,
-        44100.,
-    );
-    println!("{}", t.elapsed().as_secs_f32());
-
     let mut in_file = File::open(path).unwrap();
-    let mut audio = Audio::from_wav(&mut in_file).unwrap();
+    let audio = Audio::from_wav(&mut in_file).unwrap();
 
     fn normalize(xs: &[f32]) -> Vec<f32> {
         let norm = 1. / xs.iter().fold(0., |a: f32, &b| a.max(b));
         xs.iter().map(|x| x * norm).collect()
     }
 
-    let samples = normalize(&audio.samples[0]);
-    let kernel = normalize(
-        &kernel
-            .iter()
-            .cloned()
-            .map(|x| x as f32)
-            .collect::<Vec<f32>>(),
-    );
-    let mut planner: FftPlanner<f32> = FftPlanner::new();
-    let reverbed = rfft_convolve(&samples, &kernel, &mut planner);
-    let reverbed = normalize(&reverbed);
-
-    audio.samples[0] = reverbed.clone();
-    audio.samples[1] = reverbed;
+    // one speaker per input channel, spread across the room, and a stereo pair of microphones at the far end
+    let n_inputs = audio.samples.len();
+    let speakers: Vec<Point<f32>> = (0..n_inputs)
+        .map(|i| Point::new(10. * (i as f32 + 1.) / (n_inputs as f32 + 1.), 5.0, 1.0))
+        .collect();
+
+    let parameters = TracingParameters {
+        samples: 1000,
+        max_bounces: 1000,
+        max_delay: 100.,
+        speed_of_sound: 343.,
+        decay: 0.01,
+        base_impulse: 1000.,
+        sample_rate: IMPULSE_RESPONSE_SAMPLE_RATE as f32,
+    };
+    // a fixed seed, so that the room sounds the same on every run
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = Instant::now();
+    let kernels: Vec<Vec<Vec<f32>>> = microphones
+        .iter()
+        .map(|microphone| {
+            speakers
+                .iter()
+                .map(|speaker| {
+                    let kernel = profile_room(&geometry, speaker, microphone, &parameters, &mut rng);
+                    resample(&kernel, IMPULSE_RESPONSE_SAMPLE_RATE, audio.header.sampling_rate)
+                })
+                .collect()
+        })
+        .collect();
+    println!("{}", t.elapsed().as_secs_f32());
+
+    // scale every path by the same amount so that the balance between them is kept
+    let peak = kernels.iter().flatten().flatten().fold(0., |a: f32, &b| a.max(b));
+    let kernels = kernels
+        .into_iter()
+        .map(|row| row.into_iter().map(|k| k.into_iter().map(|x| x / peak).collect()).collect())
+        .collect();
+    let mut convolver = MatrixConvolver::new(kernels);
+
+    let mut reverbed_audio = audio;
+    reverbed_audio.apply(normalize);
+    reverbed_audio.convolve(&mut convolver, ChannelLayout::Stereo).unwrap();
+    let peak = reverbed_audio.samples.iter().flatten().fold(0., |a: f32, &b| a.max(b));
+    reverbed_audio.apply_in_place(|_, samples| samples.iter_mut().for_each(|x| *x /= peak));
 
     let mut out_file = File::create("data/reverb_out.wav").unwrap();
-    audio.to_wav(&mut out_file).unwrap();
+    reverbed_audio.to_wav(&mut out_file).unwrap();
 }
@@ -0,0 +1,218 @@
+use std::f64::consts::PI;
+use std::io::{Error, ErrorKind};
+
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::audio::{Audio, Header, WAV_FORMAT_IEEE_FLOAT};
+use crate::convolution::rfft_convolve;
+
+// length of the raised cosine fades at either end of the sweep, which keep its edges from ringing across the
+// whole measured response
+const FADE_SECONDS: f64 = 0.01;
+
+/// An exponential sine sweep for measuring impulse responses with Farina's method. The sweep is played through
+/// the system being measured and the recording is convolved with the sweep's inverse filter. Because the sweep's
+/// frequency rises exponentially, each harmonic distortion product of the system comes out of the deconvolution
+/// as its own impulse response, ahead of the linear one, where it can be cut away or kept for analysis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SineSweep {
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    pub duration: f32,
+    pub sample_rate: u32,
+}
+
+impl SineSweep {
+    /// Sweeps from `start_frequency` up to `end_frequency` Hz over `duration` seconds.
+    pub fn new(start_frequency: f32, end_frequency: f32, duration: f32, sample_rate: u32) -> SineSweep {
+        assert!(
+            0. < start_frequency && start_frequency < end_frequency,
+            "Sweep frequencies must be positive and increasing."
+        );
+        assert!(
+            end_frequency <= sample_rate as f32 / 2.,
+            "Sweep end frequency must be at most the Nyquist frequency."
+        );
+        assert!(duration > 0., "Sweep duration must be positive.");
+        SineSweep {
+            start_frequency,
+            end_frequency,
+            duration,
+            sample_rate,
+        }
+    }
+
+    /// The number of samples in the sweep and in its inverse filter.
+    pub fn len(&self) -> usize {
+        (self.duration as f64 * self.sample_rate as f64).round() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the log of the sweep's frequency ratio, which sets how fast it rises
+    fn rate(&self) -> f64 {
+        (self.end_frequency as f64 / self.start_frequency as f64).ln()
+    }
+
+    pub fn generate(&self) -> Vec<f32> {
+        let len = self.len();
+        let rate = self.rate();
+        let duration = self.duration as f64;
+        let phase_scale = 2. * PI * self.start_frequency as f64 * duration / rate;
+        let fade_len = ((FADE_SECONDS * self.sample_rate as f64) as usize).min(len / 2).max(1);
+        (0..len)
+            .map(|i| {
+                let t = i as f64 / self.sample_rate as f64;
+                let x = (phase_scale * ((t * rate / duration).exp() - 1.)).sin();
+                let edge = i.min(len - 1 - i);
+                let fade = if edge < fade_len {
+                    0.5 - 0.5 * (PI * edge as f64 / fade_len as f64).cos()
+                } else {
+                    1.
+                };
+                (x * fade) as f32
+            })
+            .collect()
+    }
+
+    /// The time-reversed sweep, with its amplitude falling by 6 dB per octave to undo the sweep's pink spectrum,
+    /// scaled so that convolving it with the sweep gives unit gain in the middle of the sweep's band.
+    pub fn inverse_filter(&self) -> Vec<f32> {
+        let sweep = self.generate();
+        let len = sweep.len();
+        let rate = self.rate();
+        let inverse: Vec<f64> = sweep
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, &x)| x as f64 * (-(i as f64) * rate / len as f64).exp())
+            .collect();
+
+        let center = (self.start_frequency as f64 * self.end_frequency as f64).sqrt();
+        let omega = 2. * PI * center / self.sample_rate as f64;
+        let dft = |xs: &mut dyn Iterator<Item = f64>| -> Complex<f64> {
+            xs.enumerate().map(|(n, x)| Complex::from_polar(x, -omega * n as f64)).sum()
+        };
+        let gain = (dft(&mut sweep.iter().map(|&x| x as f64)) * dft(&mut inverse.iter().cloned())).norm();
+        inverse.iter().map(|x| (x / gain) as f32).collect()
+    }
+
+    /// The sweep as mono, 32 bit float audio, ready to be written out and played back.
+    pub fn to_audio(self) -> Audio {
+        Audio {
+            samples: vec![self.generate()],
+            header: Header::new(WAV_FORMAT_IEEE_FLOAT, 1, self.sample_rate, 32),
+            bit_depth: 32,
+        }
+    }
+
+    /// Convolves a recording of the sweep with the inverse filter. The linear impulse response starts at
+    /// `len() - 1`, and the response of harmonic `order` starts `harmonic_offset(order)` samples before it.
+    pub fn deconvolve(&self, recording: &[f32], planner: &mut FftPlanner<f32>) -> Vec<f32> {
+        rfft_convolve(recording, &self.inverse_filter(), planner)
+    }
+
+    /// How many samples ahead of the linear impulse response the response of harmonic `order` comes out of
+    /// `deconvolve`, where order 1 is the linear response itself.
+    pub fn harmonic_offset(&self, order: usize) -> usize {
+        assert!(order > 0, "Harmonic order must be at least 1.");
+        (self.duration as f64 * (order as f64).ln() / self.rate() * self.sample_rate as f64).round() as usize
+    }
+
+    /// Cuts the responses of harmonics 1 to `orders` out of a deconvolved recording, each `len` samples long.
+    /// Harmonic responses are cut short, and padded with zeros, where they would run into the next lower
+    /// harmonic's.
+    pub fn separate_harmonics(&self, deconvolved: &[f32], orders: usize, len: usize) -> Vec<Vec<f32>> {
+        let linear_start = self.len() - 1;
+        (1..=orders)
+            .map(|order| {
+                let offset = self.harmonic_offset(order);
+                let mut response = vec![0.; len];
+                if offset > linear_start {
+                    return response;
+                }
+                let start = linear_start - offset;
+                let available = if order == 1 {
+                    len
+                } else {
+                    offset - self.harmonic_offset(order - 1)
+                };
+                let end = deconvolved.len().min(start + len.min(available));
+                if start < end {
+                    response[..end - start].copy_from_slice(&deconvolved[start..end]);
+                }
+                response
+            })
+            .collect()
+    }
+
+    /// Deconvolves each channel of a recording of the sweep into its linear impulse response, `len` samples long.
+    pub fn impulse_response(&self, recording: &Audio, len: usize) -> Result<Audio, Error> {
+        if recording.header.sampling_rate != self.sample_rate {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The recording should have the same sample rate as the sweep.",
+            ));
+        }
+        let mut planner = FftPlanner::new();
+        let samples = recording
+            .samples
+            .iter()
+            .map(|channel| {
+                let deconvolved = self.deconvolve(channel, &mut planner);
+                self.separate_harmonics(&deconvolved, 1, len).remove(0)
+            })
+            .collect();
+        Ok(Audio {
+            samples,
+            header: recording.header,
+            bit_depth: recording.bit_depth,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use crate::convolution::rfft_convolve;
+
+    use super::SineSweep;
+
+    fn peak(xs: &[f32]) -> f32 {
+        xs.iter().fold(0., |a: f32, &b| a.max(b.abs()))
+    }
+
+    #[test]
+    fn test_measures_impulse_response() {
+        let sweep = SineSweep::new(20., 3900., 1., 8000);
+        let mut system = vec![0.; 200];
+        system[10] = 1.;
+        system[50] = 0.5;
+        system[150] = -0.25;
+        let recording = rfft_convolve(&sweep.generate(), &system, &mut FftPlanner::new());
+
+        let deconvolved = sweep.deconvolve(&recording, &mut FftPlanner::new());
+        let response = &sweep.separate_harmonics(&deconvolved, 1, 300)[0];
+        for (i, (&r, &s)) in response.iter().zip(system.iter().chain(std::iter::repeat(&0.))).enumerate() {
+            assert!((r - s).abs() < 0.1, "sample {}: {} != {}", i, r, s);
+        }
+    }
+
+    #[test]
+    fn test_separates_harmonics() {
+        let sweep = SineSweep::new(50., 1500., 2., 8000);
+        // a memoryless nonlinearity, whose square term adds a second harmonic of amplitude 0.1
+        let recording: Vec<f32> = sweep.generate().iter().map(|&x| x + 0.2 * x * x).collect();
+
+        let deconvolved = sweep.deconvolve(&recording, &mut FftPlanner::new());
+        let harmonics = sweep.separate_harmonics(&deconvolved, 3, 200);
+        // the responses are limited to the sweep's band, so compare their peaks rather than expect unit impulses
+        let linear = peak(&harmonics[0]);
+        assert!(linear > 0.2);
+        assert!(peak(&harmonics[1]) / linear > 0.05 && peak(&harmonics[1]) / linear < 0.15);
+        assert!(peak(&harmonics[2]) / linear < 0.01);
+    }
+}